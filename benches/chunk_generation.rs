@@ -0,0 +1,83 @@
+//! Compares generating a patch of chunks, three chunks deep, with the two ways of
+//! caching the terrain's noise: a fresh `HashMap` of columns for every chunk, as
+//! `get_voxel_fn` used to have, against one `Heightmap` per column of chunks shared
+//! through the `HeightmapCache`, as it does now.
+//!
+//! Run with `cargo bench --bench chunk_generation --features headless`.
+
+use bevy::prelude::*;
+use bevy::utils::HashMap;
+use bevy_voxel_world::prelude::*;
+use criterion::{criterion_group, criterion_main, Criterion};
+use voxel_vegetables::{get_voxel_fn, HeightmapCache, TerrainGenerator, WorldGenSettings};
+
+const CHUNK_SIZE: i32 = 32;
+/// Chunk columns along each side of the patch
+const PATCH_SIZE: i32 = 3;
+const CHUNK_LAYERS: std::ops::RangeInclusive<i32> = -1..=1;
+
+/// Every chunk key in the patch
+fn chunk_keys() -> impl Iterator<Item = IVec3> {
+    (0..PATCH_SIZE).flat_map(|x| {
+        (0..PATCH_SIZE).flat_map(move |z| CHUNK_LAYERS.map(move |y| IVec3::new(x, y, z)))
+    })
+}
+
+/// Looks up every voxel in the chunk, and the ones just around it, as meshing does
+fn generate_chunk(chunk_key: IVec3, mut lookup: impl FnMut(IVec3) -> WorldVoxel) -> usize {
+    let min = chunk_key * CHUNK_SIZE - IVec3::ONE;
+    (0..CHUNK_SIZE + 2)
+        .flat_map(|x| {
+            (0..CHUNK_SIZE + 2)
+                .flat_map(move |y| (0..CHUNK_SIZE + 2).map(move |z| min + IVec3::new(x, y, z)))
+        })
+        .filter(|pos| lookup(*pos) != WorldVoxel::Air)
+        .count()
+}
+
+fn per_chunk_hashmap(terrain: &TerrainGenerator) -> usize {
+    chunk_keys()
+        .map(|chunk_key| {
+            let mut cache = HashMap::new();
+            generate_chunk(chunk_key, |pos| {
+                let column = *cache
+                    .entry((pos.x, pos.z))
+                    .or_insert_with(|| terrain.column(pos.x, pos.z));
+                terrain.voxel(pos, &column)
+            })
+        })
+        .sum()
+}
+
+fn shared_heightmaps(terrain: &TerrainGenerator) -> usize {
+    let cache = HeightmapCache::default();
+    chunk_keys()
+        .map(|chunk_key| {
+            generate_chunk(
+                chunk_key,
+                get_voxel_fn(terrain.clone(), cache.clone(), chunk_key),
+            )
+        })
+        .sum()
+}
+
+fn bench_chunk_generation(c: &mut Criterion) {
+    let terrain = TerrainGenerator::new(&WorldGenSettings {
+        seed: 1,
+        ..default()
+    });
+    assert_eq!(per_chunk_hashmap(&terrain), shared_heightmaps(&terrain));
+
+    let mut group = c.benchmark_group("chunk_generation");
+    group.sample_size(10);
+    group.bench_function("per_chunk_hashmap", |b| {
+        b.iter(|| per_chunk_hashmap(&terrain))
+    });
+    group.bench_function("shared_heightmaps", |b| {
+        b.iter(|| shared_heightmaps(&terrain))
+    });
+    group.finish();
+}
+
+criterion_group!(benches, bench_chunk_generation);
+criterion_main!(benches);
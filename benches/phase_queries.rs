@@ -0,0 +1,194 @@
+//! Runs the lifecycle systems over 100k organisms spread across every life phase,
+//! through the `HeadlessSimPlugin`, with the whole farm processed every frame and
+//! with the default `LifeCycleBudget`.
+//!
+//! Also compares the two ways of finding organisms in a given life phase: filtering
+//! on the `LifePhase` held in a component, as the lifecycle systems used to, against
+//! the phase marker components with `With<>` filters, as they do now.
+//!
+//! Run with `cargo bench --bench phase_queries --features headless`.
+
+use bevy::ecs::schedule::ExecutorKind;
+use bevy::ecs::system::CommandQueue;
+use bevy::prelude::*;
+use bevy::time::TimeUpdateStrategy;
+use bevy_voxel_world::prelude::*;
+use criterion::{criterion_group, criterion_main, Criterion};
+use std::path::Path;
+use std::time::Duration;
+use voxel_vegetables::{
+    spawn_organism, BlockType, Dead, Fruiting, Germinated, Growing, HeadlessSimPlugin,
+    HeadlessVoxels, LifeCycleBudget, LifePhase, Mature, Pollinated, Seed, SpeciesRegistry,
+    VoxelAccess,
+};
+
+const ORGANISMS: usize = 100_000;
+/// Organisms are planted in a square field this many columns across
+const FIELD_SIZE: i32 = 317;
+const PHASES: usize = 7;
+
+/// Every phase, with the needs of the species it is for
+fn phases(registry: &SpeciesRegistry, name: &str) -> Vec<LifePhase> {
+    let def = registry.get(registry.by_name(name).unwrap());
+    vec![
+        LifePhase::Seed,
+        LifePhase::Germinated,
+        LifePhase::Growing {
+            needs: def.growing_needs.clone(),
+        },
+        LifePhase::Mature,
+        LifePhase::Pollinated {
+            needs: def.fruiting_needs.clone(),
+        },
+        LifePhase::Fruiting,
+        LifePhase::Death,
+    ]
+}
+
+fn field(ix: usize) -> IVec2 {
+    IVec2::new(ix as i32 % FIELD_SIZE, ix as i32 / FIELD_SIZE)
+}
+
+/// A headless farm of wheat on a field of dirt, an equal share of it in each phase
+fn farm(organisms_per_frame: usize) -> App {
+    let mut app = App::new();
+    app.add_plugins((MinimalPlugins, HeadlessSimPlugin::default()))
+        .insert_resource(LifeCycleBudget {
+            organisms_per_frame,
+        })
+        .insert_resource(TimeUpdateStrategy::ManualDuration(Duration::from_millis(
+            16,
+        )));
+
+    let dirt = WorldVoxel::Solid(BlockType::Dirt.index());
+    let mut voxels = app.world.resource_mut::<HeadlessVoxels>();
+    (0..ORGANISMS).for_each(|ix| {
+        let column = field(ix);
+        voxels.set_voxel(IVec3::new(column.x, 0, column.y), dirt);
+    });
+
+    let registry = app.world.resource::<SpeciesRegistry>();
+    let wheat = registry.by_name("Wheat").unwrap();
+    let phases = phases(registry, "Wheat");
+    let mut queue = CommandQueue::default();
+    let mut commands = Commands::new(&mut queue, &app.world);
+    (0..ORGANISMS).for_each(|ix| {
+        let column = field(ix);
+        spawn_organism(
+            &mut commands,
+            wheat,
+            phases[ix % PHASES].clone(),
+            IVec3::new(column.x, 1, column.y),
+            None,
+        );
+    });
+    queue.apply(&mut app.world);
+    // Indexes the organisms, so the frames measured are all alike
+    app.update();
+    app
+}
+
+/// The `LifePhase` an organism is spawned and saved with, held in a component
+#[derive(Component)]
+struct PhaseComponent(LifePhase);
+
+#[derive(Component)]
+struct Countdown(f32);
+
+fn phase_index(phase: &LifePhase) -> usize {
+    match phase {
+        LifePhase::Seed => 0,
+        LifePhase::Germinated => 1,
+        LifePhase::Growing { .. } => 2,
+        LifePhase::Mature => 3,
+        LifePhase::Pollinated { .. } => 4,
+        LifePhase::Fruiting => 5,
+        LifePhase::Death => 6,
+    }
+}
+
+fn enum_system<const PHASE: usize>(mut query: Query<(&PhaseComponent, &mut Countdown)>) {
+    query
+        .iter_mut()
+        .filter(|(PhaseComponent(phase), _)| phase_index(phase) == PHASE)
+        .for_each(|(_, mut countdown)| countdown.0 -= 0.016);
+}
+
+fn marker_system<T: Component>(mut query: Query<&mut Countdown, With<T>>) {
+    query.for_each_mut(|mut countdown| countdown.0 -= 0.016);
+}
+
+fn schedule() -> Schedule {
+    let mut schedule = Schedule::default();
+    schedule.set_executor_kind(ExecutorKind::SingleThreaded);
+    schedule
+}
+
+fn enum_world(phases: &[LifePhase]) -> (World, Schedule) {
+    let mut world = World::new();
+    world.spawn_batch(
+        (0..ORGANISMS).map(|ix| (PhaseComponent(phases[ix % PHASES].clone()), Countdown(1.))),
+    );
+    let mut schedule = schedule();
+    schedule.add_systems((
+        enum_system::<0>,
+        enum_system::<1>,
+        enum_system::<2>,
+        enum_system::<3>,
+        enum_system::<4>,
+        enum_system::<5>,
+        enum_system::<6>,
+    ));
+    (world, schedule)
+}
+
+fn marker_world(phases: &[LifePhase]) -> (World, Schedule) {
+    let mut world = World::new();
+    let mut queue = CommandQueue::default();
+    let mut commands = Commands::new(&mut queue, &world);
+    (0..ORGANISMS).for_each(|ix| {
+        phases[ix % PHASES]
+            .clone()
+            .insert_into(&mut commands.spawn(Countdown(1.)))
+    });
+    queue.apply(&mut world);
+    let mut schedule = schedule();
+    schedule.add_systems((
+        marker_system::<Seed>,
+        marker_system::<Germinated>,
+        marker_system::<Growing>,
+        marker_system::<Mature>,
+        marker_system::<Pollinated>,
+        marker_system::<Fruiting>,
+        marker_system::<Dead>,
+    ));
+    (world, schedule)
+}
+
+fn lifecycle_frames(c: &mut Criterion) {
+    let mut group = c.benchmark_group("lifecycle frame, 100k organisms");
+    group.sample_size(10);
+    let mut app = farm(ORGANISMS);
+    group.bench_function("every organism each frame", |b| b.iter(|| app.update()));
+    let mut app = farm(LifeCycleBudget::default().organisms_per_frame);
+    group.bench_function("default budget", |b| b.iter(|| app.update()));
+    group.finish();
+}
+
+fn phase_queries(c: &mut Criterion) {
+    let registry = SpeciesRegistry::from_dir(Path::new("assets/species")).unwrap();
+    let phases = phases(&registry, "Wheat");
+    let mut group = c.benchmark_group("phase queries, 100k organisms");
+    let (mut world, mut schedule) = enum_world(&phases);
+    group.bench_function("LifePhase component filtered with matches", |b| {
+        b.iter(|| schedule.run(&mut world))
+    });
+    let (mut world, mut schedule) = marker_world(&phases);
+    group.bench_function("marker components with With<>", |b| {
+        b.iter(|| schedule.run(&mut world))
+    });
+    group.finish();
+}
+
+criterion_group!(benches, lifecycle_frames, phase_queries);
+criterion_main!(benches);
@@ -19,6 +19,20 @@ fn main() {
         "wheat_side.png",
         "wheat_top.png",
         "wheat_sprouts.png",
+        "dirt_grass.png",
+        "sand.png",
+        "snow_top.png",
+        "dirt_snow.png",
+        "gravel_dirt.png",
+        "forest_floor.png",
+        "mud.png",
+        "subsoil.png",
+        "stone.png",
+        "bedrock.png",
+        "tilled_soil.png",
+        "bark.png",
+        "leaves.png",
+        "apple.png",
     ]; // Example filenames
 
     // Load the first image to get width and height
@@ -23,6 +23,9 @@ pub struct Actions {
     pub player_movement: Vec<MovementControl>,
     pub left_click_crosshair: bool,
     pub open_menu: bool,
+    pub save_game: bool,
+    pub load_game: bool,
+    pub select_save_slot: Option<u8>,
 }
 
 pub fn set_playing_actions(
@@ -63,4 +66,19 @@ pub fn set_playing_actions(
     actions.open_menu = game_controls_from_keys
         .iter()
         .any(|x| *x == GameControl::OpenMenu);
+
+    // These should only fire once per key press, not on every frame it is held
+    let game_controls_just_pressed: Vec<GameControl> = keyboard_input
+        .get_just_pressed()
+        .filter_map(|key_code| GameControl::from_key_code(*key_code))
+        .collect();
+    actions.save_game = game_controls_just_pressed.contains(&GameControl::SaveGame);
+    actions.load_game = game_controls_just_pressed.contains(&GameControl::LoadGame);
+    actions.select_save_slot =
+        game_controls_just_pressed
+            .iter()
+            .find_map(|game_control| match game_control {
+                GameControl::SelectSaveSlot(slot) => Some(*slot),
+                _ => None,
+            });
 }
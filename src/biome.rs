@@ -0,0 +1,94 @@
+use crate::block_types::BlockType;
+use crate::core_components::*;
+use crate::lifecycles::LifeCycleSet;
+use crate::map_setup::TerrainGenerator;
+use bevy::prelude::*;
+use serde::Deserialize;
+use strum_macros::Display;
+
+/// Climate below this is cold enough for snow
+const ALPINE_TEMPERATURE: f64 = 0.15;
+/// Climate above this moisture is waterlogged
+const WETLAND_MOISTURE: f64 = 0.75;
+const FOREST_MOISTURE: f64 = 0.55;
+/// Climate below this moisture is too dry for much more than scrub
+const ARID_MOISTURE: f64 = 0.3;
+
+/// Gives organisms the `Biome` of the column they grow in, from the generated terrain
+pub struct BiomePlugin;
+
+impl Plugin for BiomePlugin {
+    fn build(&self, app: &mut App) {
+        app.register_type::<Biome>().add_systems(
+            Update,
+            assign_biome_system
+                .before(LifeCycleSet)
+                .run_if(resource_exists::<TerrainGenerator>()),
+        );
+    }
+}
+
+/// The kind of land a column is, from its climate; see `TerrainGenerator::biome`.
+/// Also an organism's component, for the column it grows in.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, Component, Reflect, Deserialize, Display)]
+#[reflect(Component)]
+pub enum Biome {
+    Meadow,
+    Forest,
+    Wetland,
+    Arid,
+    Alpine,
+}
+
+impl Biome {
+    /// `temperature` and `moisture` are roughly between 0 and 1. Cold trumps
+    /// everything else, then how wet or dry the climate is.
+    pub fn from_climate(temperature: f64, moisture: f64) -> Biome {
+        if temperature < ALPINE_TEMPERATURE {
+            Biome::Alpine
+        } else if moisture > WETLAND_MOISTURE {
+            Biome::Wetland
+        } else if moisture > FOREST_MOISTURE {
+            Biome::Forest
+        } else if moisture < ARID_MOISTURE {
+            Biome::Arid
+        } else {
+            Biome::Meadow
+        }
+    }
+
+    /// The block covering the ground, above the beaches
+    pub fn surface_block(self) -> BlockType {
+        match self {
+            Biome::Meadow => BlockType::Grass,
+            Biome::Forest => BlockType::ForestFloor,
+            Biome::Wetland => BlockType::Mud,
+            Biome::Arid => BlockType::Gravel,
+            Biome::Alpine => BlockType::Snow,
+        }
+    }
+}
+
+fn assign_biome_system(
+    mut commands: Commands,
+    terrain: Res<TerrainGenerator>,
+    query: Query<(Entity, &HasPosition), (With<Species>, Without<Biome>)>,
+) {
+    query.for_each(|(entity, HasPosition { pos })| {
+        commands.entity(entity).insert(terrain.biome(pos.x, pos.z));
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::biome::*;
+
+    #[test]
+    fn cold_trumps_moisture() {
+        assert_eq!(Biome::from_climate(0.1, 0.9), Biome::Alpine);
+        assert_eq!(Biome::from_climate(0.5, 0.9), Biome::Wetland);
+        assert_eq!(Biome::from_climate(0.5, 0.6), Biome::Forest);
+        assert_eq!(Biome::from_climate(0.5, 0.4), Biome::Meadow);
+        assert_eq!(Biome::from_climate(0.5, 0.1), Biome::Arid);
+    }
+}
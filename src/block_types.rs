@@ -2,6 +2,7 @@ use std::u8;
 
 use bevy::utils::HashMap;
 use lazy_static::*;
+use serde::Deserialize;
 use strum_macros::*;
 // BlockType maps to VoxTexture; see Block_TO_TILES_MAP below
 #[derive(Clone, Copy, Debug, EnumIter, EnumCount, FromRepr)]
@@ -18,6 +19,20 @@ pub enum VoxTexture {
     WheatSide,
     WheatTop,
     WheatSprouts,
+    GrassSide,
+    Sand,
+    SnowTop,
+    SnowSide,
+    Gravel,
+    ForestFloor,
+    Mud,
+    Subsoil,
+    Stone,
+    Bedrock,
+    TilledSoil,
+    Bark,
+    Leaves,
+    Apple,
 }
 
 impl VoxTexture {
@@ -43,7 +58,7 @@ impl VoxTextureArray {
     }
 }
 
-#[derive(Clone, Copy, Debug, EnumIter, EnumCount, FromRepr, PartialEq, Eq, Hash)]
+#[derive(Clone, Copy, Debug, EnumIter, EnumCount, FromRepr, PartialEq, Eq, Hash, Deserialize)]
 #[repr(u8)]
 pub enum BlockType {
     Grass,
@@ -55,6 +70,19 @@ pub enum BlockType {
     AppleSapling,
     AppleTree,
     DeadTree,
+    Wilting,
+    TreeTrunk,
+    Leaves,
+    Apple,
+    Sand,
+    Snow,
+    Gravel,
+    ForestFloor,
+    Mud,
+    Subsoil,
+    Stone,
+    Bedrock,
+    TilledSoil,
     // AppleTreeWithApples,
     Nothing, // Keep Nothing as last in enum
 }
@@ -68,6 +96,19 @@ impl BlockType {
     pub fn from_unsafe(ix: u8) -> Self {
         BlockType::from_repr(ix).unwrap()
     }
+    /// Plants can be planted on top of these, but not on the subsoil and stone below
+    pub fn is_topsoil(self) -> bool {
+        matches!(
+            self,
+            BlockType::Dirt
+                | BlockType::Grass
+                | BlockType::Snow
+                | BlockType::Gravel
+                | BlockType::ForestFloor
+                | BlockType::Mud
+                | BlockType::TilledSoil
+        )
+    }
 }
 
 lazy_static! {
@@ -77,7 +118,7 @@ lazy_static! {
         let mut tmap = HashMap::new();
         tmap.insert(
             BlockType::Grass,
-            VoxTextureArray([VoxTexture::Grass, VoxTexture::Grass, VoxTexture::Grass]),
+            VoxTextureArray([VoxTexture::Grass, VoxTexture::GrassSide, VoxTexture::Dirt]),
         );
         tmap.insert(
             BlockType::Dirt,
@@ -111,6 +152,58 @@ lazy_static! {
             BlockType::DeadTree,
             VoxTextureArray([VoxTexture::Dirt, VoxTexture::Dirt, VoxTexture::Dirt]),
         );
+        tmap.insert(
+            BlockType::Wilting,
+            VoxTextureArray([VoxTexture::GrassBrown, VoxTexture::GrassBrown, VoxTexture::Nothing]),
+        );
+        tmap.insert(
+            BlockType::TreeTrunk,
+            VoxTextureArray([VoxTexture::Bark, VoxTexture::Bark, VoxTexture::Bark]),
+        );
+        tmap.insert(
+            BlockType::Leaves,
+            VoxTextureArray([VoxTexture::Leaves, VoxTexture::Leaves, VoxTexture::Leaves]),
+        );
+        tmap.insert(
+            BlockType::Apple,
+            VoxTextureArray([VoxTexture::Apple, VoxTexture::Apple, VoxTexture::Apple]),
+        );
+        tmap.insert(
+            BlockType::Sand,
+            VoxTextureArray([VoxTexture::Sand, VoxTexture::Sand, VoxTexture::Sand]),
+        );
+        tmap.insert(
+            BlockType::Snow,
+            VoxTextureArray([VoxTexture::SnowTop, VoxTexture::SnowSide, VoxTexture::Dirt]),
+        );
+        tmap.insert(
+            BlockType::Gravel,
+            VoxTextureArray([VoxTexture::Gravel, VoxTexture::Gravel, VoxTexture::Gravel]),
+        );
+        tmap.insert(
+            BlockType::ForestFloor,
+            VoxTextureArray([VoxTexture::ForestFloor, VoxTexture::GrassSide, VoxTexture::Dirt]),
+        );
+        tmap.insert(
+            BlockType::Mud,
+            VoxTextureArray([VoxTexture::Mud, VoxTexture::Mud, VoxTexture::Mud]),
+        );
+        tmap.insert(
+            BlockType::Subsoil,
+            VoxTextureArray([VoxTexture::Subsoil, VoxTexture::Subsoil, VoxTexture::Subsoil]),
+        );
+        tmap.insert(
+            BlockType::Stone,
+            VoxTextureArray([VoxTexture::Stone, VoxTexture::Stone, VoxTexture::Stone]),
+        );
+        tmap.insert(
+            BlockType::Bedrock,
+            VoxTextureArray([VoxTexture::Bedrock, VoxTexture::Bedrock, VoxTexture::Bedrock]),
+        );
+        tmap.insert(
+            BlockType::TilledSoil,
+            VoxTextureArray([VoxTexture::TilledSoil, VoxTexture::Dirt, VoxTexture::Dirt]),
+        );
         tmap.insert(
             BlockType::Nothing,
             VoxTextureArray([VoxTexture::Dirt, VoxTexture::Dirt, VoxTexture::Dirt]),
@@ -1,6 +1,7 @@
-use bevy::{prelude::*, utils::HashMap};
+use bevy::{ecs::system::EntityCommands, prelude::*, utils::HashMap};
+use serde::{Deserialize, Serialize};
 
-use crate::{block_types::BlockType, GameState};
+use crate::GameState;
 
 #[derive(Component)]
 pub struct ChangeState(pub GameState);
@@ -16,16 +17,35 @@ pub struct OwnedBy {
     pub owner: Entity,
 }
 
-#[derive(Eq, PartialEq, Clone, Debug, Component, Deref)]
+#[derive(Eq, PartialEq, Clone, Debug, Component, Deref, Serialize, Deserialize)]
+#[serde(transparent)]
 pub struct Water(pub u32); // Representing quantity of water currently accessible
 
-#[derive(Eq, PartialEq, Clone, Debug, Component, Deref)]
+/// How much `Water` over 1 speeds up growth, per unit
+const WATER_GROWTH_BONUS: f32 = 0.25;
+
+impl Water {
+    /// Well watered plants meet their light and time needs faster
+    pub fn growth_rate(&self) -> f32 {
+        1. + self.0.saturating_sub(1) as f32 * WATER_GROWTH_BONUS
+    }
+}
+
+/// How moist the ground an organism grows in is, see `hydrology::moisture`.
+/// Its `Water` settles here in clear weather.
+#[derive(Eq, PartialEq, Clone, Copy, Debug, Component, Reflect, Deref)]
+#[reflect(Component)]
+pub struct Moisture(pub u32);
+
+#[derive(Eq, PartialEq, Clone, Debug, Component, Deref, Serialize, Deserialize)]
+#[serde(transparent)]
 pub struct Soil(pub u32); // Representing quantity of soil currently accessible
 
 /// Used twice: for initial germination, and to go from germination to growing
 #[derive(Clone, Debug, Component, Deref)]
 pub struct GerminationTimer(pub f32); // Timer to track time for germination
 
+#[derive(Clone, Debug, Deserialize)]
 pub struct GerminationNeeds {
     pub water: Water,
     pub soil: Soil,
@@ -34,8 +54,16 @@ pub struct GerminationNeeds {
 #[derive(Clone, Debug, Component)]
 pub struct MatureAgeTimer(pub f32);
 
+/// Left on a cross-pollinating organism that found no partner when its
+/// `MatureAgeTimer` ran out; it stays `Mature` and tries again later
+#[derive(Clone, Debug, Default, Component, Reflect)]
+#[reflect(Component)]
+pub struct PollinationFailed {
+    pub attempts: u32,
+}
+
 /// Unlike GerminationNeeds, GrowingNeeds are cumulative
-#[derive(PartialEq, Clone, Debug)]
+#[derive(PartialEq, Clone, Debug, Serialize, Deserialize)]
 pub struct GrowingNeeds {
     pub water: Water,
     pub soil: Soil,
@@ -43,7 +71,24 @@ pub struct GrowingNeeds {
     pub time: f32,
 }
 
-#[derive(PartialEq, Clone, Debug)]
+impl GrowingNeeds {
+    /// How far `self`, the needs still to be met, has come from `total`, between 0 and 1.
+    /// Light and time are both needed, so the one furthest behind counts.
+    pub fn progress(&self, total: &GrowingNeeds) -> f32 {
+        let done = |remaining: f32, total: f32| {
+            if total > 0. {
+                1. - remaining.max(0.) / total
+            } else {
+                1.
+            }
+        };
+        done(self.light, total.light)
+            .min(done(self.time, total.time))
+            .clamp(0., 1.)
+    }
+}
+
+#[derive(PartialEq, Clone, Debug, Serialize, Deserialize)]
 pub struct FruitingNeeds {
     pub water: Water,
     pub soil: Soil,
@@ -51,168 +96,208 @@ pub struct FruitingNeeds {
     pub time: f32,
 }
 
-#[derive(Eq, PartialEq, Hash, Clone, Debug, Component)]
-pub enum Species {
-    Apple,
-    Wheat,
+/// An id into the `SpeciesRegistry`, which holds the species definitions
+#[derive(Eq, PartialEq, Hash, Clone, Copy, Debug, Component)]
+pub struct Species(pub u16);
+
+/// Names an organism's phase when spawning or saving it. On the organism itself
+/// each phase is its own component (`Seed`, `Germinated`, ..., `Dead`), so the
+/// lifecycle systems only visit the archetypes they care about.
+#[derive(PartialEq, Clone, Debug, Serialize, Deserialize)]
+pub enum LifePhase {
+    Seed,
+    Germinated,
+    Growing { needs: GrowingNeeds },
+    Mature,
+    Pollinated { needs: FruitingNeeds },
+    Fruiting,
+    Death,
 }
 
-impl Species {
-    pub fn germination_needs(&self) -> GerminationNeeds {
-        match self {
-            Species::Apple => GerminationNeeds {
-                water: Water(1),
-                soil: Soil(1),
-            },
-            Species::Wheat => GerminationNeeds {
-                water: Water(1),
-                soil: Soil(1),
-            },
-        }
-    }
+#[derive(PartialEq, Clone, Debug, Default, Component)]
+pub struct Seed;
 
-    pub fn growing_needs(&self) -> GrowingNeeds {
-        match self {
-            Species::Apple => GrowingNeeds {
-                water: Water(50),
-                soil: Soil(50),
-                light: 100.0,
-                time: 100.0,
-            },
-            Species::Wheat => GrowingNeeds {
-                water: Water(10),
-                soil: Soil(10),
-                light: 10.0,
-                time: 10.0,
-            },
-        }
-    }
+#[derive(PartialEq, Clone, Debug, Default, Component)]
+pub struct Germinated;
 
-    pub fn fruiting_needs(&self) -> FruitingNeeds {
-        match self {
-            Species::Apple => FruitingNeeds {
-                water: Water(20),
-                soil: Soil(20),
-                light: 40.0,
-                time: 40.0,
-            },
-            Species::Wheat => FruitingNeeds {
-                water: Water(3),
-                soil: Soil(3),
-                light: 10.0,
-                time: 30.0,
-            },
-        }
-    }
+#[derive(PartialEq, Clone, Debug, Component)]
+pub struct Growing {
+    pub needs: GrowingNeeds,
+    /// Water and soil drawn towards the next whole unit of the needs
+    pub drawn: f32,
+}
 
-    pub fn seeds_per_generation(&self) -> u32 {
-        match self {
-            Species::Apple => 15,  // Actual per tree is about 1500
-            Species::Wheat => 100, // 30412800 - actual estimate per chunk if we had real wheat
-        }
-    }
+#[derive(PartialEq, Clone, Debug, Default, Component)]
+pub struct Mature;
 
-    /// should be roughly proportional to seeds_per_generation
-    pub fn spread_per_fruiting(&self) -> u8 {
-        match self {
-            Species::Apple => 2,
-            Species::Wheat => 10,
-        }
-    }
+#[derive(PartialEq, Clone, Debug, Component)]
+pub struct Pollinated {
+    pub needs: FruitingNeeds,
+    /// Water and soil drawn towards the next whole unit of the needs
+    pub drawn: f32,
+}
 
-    pub fn spread_distance(&self) -> u8 {
-        match self {
-            Species::Apple => 10,
-            Species::Wheat => 50,
-        }
-    }
+#[derive(PartialEq, Clone, Debug, Default, Component)]
+pub struct Fruiting;
+
+#[derive(PartialEq, Clone, Debug, Default, Component)]
+pub struct Dead;
 
-    pub fn food_value(&self) -> u32 {
+/// All of the phase components; an organism has exactly one of them
+pub type PhaseComponents = (
+    Seed,
+    Germinated,
+    Growing,
+    Mature,
+    Pollinated,
+    Fruiting,
+    Dead,
+);
+
+/// Reads back whichever phase component an organism has, see `LifePhase::from_components`
+pub type AnyPhase = AnyOf<(
+    &'static Seed,
+    &'static Germinated,
+    &'static Growing,
+    &'static Mature,
+    &'static Pollinated,
+    &'static Fruiting,
+    &'static Dead,
+)>;
+
+impl LifePhase {
+    /// Swaps the organism's phase component for the one matching this phase
+    pub fn insert_into(self, entity_commands: &mut EntityCommands) {
+        entity_commands.remove::<PhaseComponents>();
         match self {
-            Species::Apple => 10,
-            Species::Wheat => 2,
-        }
+            LifePhase::Seed => entity_commands.insert(Seed),
+            LifePhase::Germinated => entity_commands.insert(Germinated),
+            LifePhase::Growing { needs } => entity_commands.insert(Growing { needs, drawn: 0. }),
+            LifePhase::Mature => entity_commands.insert(Mature),
+            LifePhase::Pollinated { needs } => {
+                entity_commands.insert(Pollinated { needs, drawn: 0. })
+            }
+            LifePhase::Fruiting => entity_commands.insert(Fruiting),
+            LifePhase::Death => entity_commands.insert(Dead),
+        };
     }
 
-    pub fn wild_organisms_per_chunk(&self) -> u16 {
-        match self {
-            Species::Apple => 1,
-            Species::Wheat => 10,
+    #[allow(clippy::type_complexity)]
+    pub fn from_components(
+        phase: (
+            Option<&Seed>,
+            Option<&Germinated>,
+            Option<&Growing>,
+            Option<&Mature>,
+            Option<&Pollinated>,
+            Option<&Fruiting>,
+            Option<&Dead>,
+        ),
+    ) -> LifePhase {
+        match phase {
+            (Some(_), ..) => LifePhase::Seed,
+            (_, Some(_), ..) => LifePhase::Germinated,
+            (_, _, Some(Growing { needs, .. }), ..) => LifePhase::Growing {
+                needs: needs.clone(),
+            },
+            (_, _, _, Some(_), ..) => LifePhase::Mature,
+            (_, _, _, _, Some(Pollinated { needs, .. }), ..) => LifePhase::Pollinated {
+                needs: needs.clone(),
+            },
+            (_, _, _, _, _, Some(_), _) => LifePhase::Fruiting,
+            _ => LifePhase::Death,
         }
     }
+}
 
-    pub fn block_type(&self, phase: &LifePhase) -> BlockType {
-        match (self, phase) {
-            (Species::Apple, LifePhase::Seed) => BlockType::SeedPlanted,
-            (Species::Apple, LifePhase::Germinated) => BlockType::SeedPlanted,
-            (Species::Apple, LifePhase::Growing { .. }) => BlockType::AppleSapling,
-            (Species::Apple, LifePhase::Mature) => BlockType::AppleTree,
-            (Species::Apple, LifePhase::Pollinated { .. }) => BlockType::AppleTree,
-            (Species::Apple, LifePhase::Fruiting) => BlockType::AppleTree,
-            (Species::Apple, LifePhase::Death) => BlockType::DeadTree,
-            (Species::Wheat, LifePhase::Seed) => BlockType::SeedPlanted,
-            (Species::Wheat, LifePhase::Germinated) => BlockType::SeedPlanted,
-            (Species::Wheat, LifePhase::Growing { .. }) => BlockType::WheatSprouts,
-            (Species::Wheat, LifePhase::Mature) => BlockType::Wheat,
-            (Species::Wheat, LifePhase::Pollinated { .. }) => BlockType::Wheat,
-            (Species::Wheat, LifePhase::Fruiting) => BlockType::Wheat,
-            (Species::Wheat, LifePhase::Death) => BlockType::Nothing,
-        }
+#[derive(PartialEq, Clone, Debug, Component, Deref)]
+pub struct Generations(pub u32);
+
+#[derive(Clone, Debug, Component, Deref)]
+pub struct DecayTimer(pub f32);
+
+/// Generations an organism fruits for before it dies; drawn when it is spawned,
+/// see `SpeciesDef::sample_lifespan`
+#[derive(PartialEq, Eq, Clone, Copy, Debug, Component, Reflect, Deref)]
+#[reflect(Component)]
+pub struct Lifespan(pub u32);
+
+/// When the lifecycle systems last processed this organism, in seconds of
+/// `Time::elapsed`; `None` until it is first processed
+#[derive(Clone, Copy, Debug, Default, Component)]
+pub struct LastProcessed(pub Option<f64>);
+
+impl LastProcessed {
+    /// Seconds since the organism was last processed, which is more than a frame
+    /// when it had to wait for its turn. Marks it as processed now.
+    pub fn advance(&mut self, time: &Time) -> f32 {
+        let now = time.elapsed_seconds_f64();
+        let elapsed = self
+            .0
+            .map_or(time.delta_seconds(), |last| (now - last) as f32);
+        self.0 = Some(now);
+        elapsed
     }
+}
 
-    // pub fn self_pollinates(&self) -> bool {
-    //     match self {
-    //         Species::Apple => false,
-    //         Species::Wheat => true,
-    //     }
-    // }
+pub const MAX_HEALTH: f32 = 100.;
+/// Below this an organism is painted as `BlockType::Wilting`
+pub const WILTING_HEALTH: f32 = 50.;
+
+/// Drops while a plant's needs aren't met and recovers once they are, through
+/// `Growing`, `Mature` and `Pollinated`; the organism dies when it reaches zero.
+/// Seeds and sprouts live off the seed, and `Fruiting` only lasts until the plant's
+/// next turn, when it is harvested.
+#[derive(PartialEq, Clone, Debug, Component, Reflect, Deref)]
+#[reflect(Component)]
+pub struct Health(pub f32);
+
+impl Default for Health {
+    fn default() -> Self {
+        Health(MAX_HEALTH)
+    }
+}
 
-    pub fn min_generations(&self) -> u32 {
-        match self {
-            Species::Apple => 10,
-            Species::Wheat => 1,
-        }
+impl Health {
+    pub fn change(&mut self, amount: f32) {
+        self.0 = (self.0 + amount).clamp(0., MAX_HEALTH);
     }
 
-    pub fn max_generations(&self) -> u32 {
-        match self {
-            Species::Apple => 200,
-            Species::Wheat => 1,
-        }
+    pub fn is_wilting(&self) -> bool {
+        self.0 < WILTING_HEALTH
     }
 
-    pub fn decay_time(&self) -> f32 {
-        match self {
-            Species::Apple => 200.,
-            Species::Wheat => 0.,
-        }
+    pub fn is_dead(&self) -> bool {
+        self.0 <= 0.
     }
 }
 
-//TODO: in general, when there are a lot of entities of a given
-// component, it probably makes sense to not use an enum for the
-// component itself, but instead for the individual variants,
-// This way the ECS can likely optimize the query
-#[derive(PartialEq, Clone, Debug, Component)]
-pub enum LifePhase {
-    Seed,
-    Germinated,
-    Growing { needs: GrowingNeeds },
-    Mature,
-    Pollinated { needs: FruitingNeeds },
-    Fruiting,
-    Death,
+/// The voxels a tree has grown besides the one at its `HasPosition`, so the
+/// whole tree can be removed when it decays. See `SpeciesDef::tree`.
+#[derive(PartialEq, Eq, Clone, Debug, Default, Component, Reflect)]
+#[reflect(Component)]
+pub struct TreeVoxels {
+    /// From the bottom up, starting above `HasPosition`
+    pub trunk: Vec<IVec3>,
+    pub canopy: Vec<IVec3>,
+    pub fruit: Vec<IVec3>,
+    /// The trunk height and canopy radius the tree last grew towards
+    pub size: (u8, u8),
 }
 
-#[derive(PartialEq, Clone, Debug, Component, Deref)]
-pub struct Generations(pub u32);
+impl TreeVoxels {
+    pub fn iter(&self) -> impl Iterator<Item = &IVec3> {
+        self.trunk.iter().chain(&self.canopy).chain(&self.fruit)
+    }
 
-#[derive(Clone, Debug, Component, Deref)]
-pub struct DecayTimer(pub f32);
+    pub fn contains(&self, pos: IVec3) -> bool {
+        self.iter().any(|voxel| *voxel == pos)
+    }
+}
 
 pub const SEED_PHASE: LifePhase = LifePhase::Seed;
 
-#[derive(PartialEq, Eq, Clone, Debug)]
+#[derive(PartialEq, Eq, Clone, Copy, Debug)]
 pub enum PaintableResources {
     SeedCrop(Species),
 }
@@ -0,0 +1,95 @@
+use crate::lifecycles::LifeCycleSet;
+use bevy::prelude::*;
+use std::f32::consts::TAU;
+
+const SUN_ILLUMINANCE: f32 = 100_000.;
+const DAY_AMBIENT_BRIGHTNESS: f32 = 0.3;
+const NIGHT_AMBIENT_BRIGHTNESS: f32 = 0.05;
+
+/// Drives the simulated time of day, which lights the scene and supplies
+/// the light available to growing plants
+pub struct DayNightPlugin {
+    /// Length of a full day and night, in seconds
+    pub day_length: f32,
+}
+
+impl Default for DayNightPlugin {
+    fn default() -> Self {
+        DayNightPlugin { day_length: 120. }
+    }
+}
+
+impl Plugin for DayNightPlugin {
+    fn build(&self, app: &mut App) {
+        app.insert_resource(TimeOfDay::new(self.day_length))
+            .add_systems(
+                Update,
+                advance_time_of_day_system
+                    .in_set(LifeCycleSet)
+                    .before(update_sun_system),
+            )
+            .add_systems(Update, update_sun_system);
+    }
+}
+
+/// Marks the directional light that follows the time of day
+#[derive(Component)]
+pub struct Sun;
+
+#[derive(Resource, Clone, Debug)]
+pub struct TimeOfDay {
+    /// Fraction of the day that has passed: 0 is midnight, 0.25 sunrise,
+    /// 0.5 noon and 0.75 sunset
+    pub fraction: f32,
+    pub day_length: f32,
+}
+
+impl TimeOfDay {
+    /// Starts at sunrise
+    pub fn new(day_length: f32) -> Self {
+        TimeOfDay {
+            fraction: 0.25,
+            day_length,
+        }
+    }
+
+    pub fn advance(&mut self, seconds: f32) {
+        if self.day_length > 0. {
+            self.fraction = (self.fraction + seconds / self.day_length).rem_euclid(1.);
+        }
+    }
+
+    /// Height of the sun, from -1 at midnight to 1 at noon
+    pub fn sun_elevation(&self) -> f32 {
+        (TAU * (self.fraction - 0.25)).sin()
+    }
+
+    /// Light available to plants: 1 for most of the day, ramping down
+    /// through dusk to 0 for the whole night
+    pub fn light(&self) -> f32 {
+        (self.sun_elevation() * 2.).clamp(0., 1.)
+    }
+}
+
+fn advance_time_of_day_system(time: Res<Time>, mut time_of_day: ResMut<TimeOfDay>) {
+    time_of_day.advance(time.delta_seconds());
+}
+
+fn update_sun_system(
+    time_of_day: Res<TimeOfDay>,
+    ambient_light: Option<ResMut<AmbientLight>>,
+    mut sun_query: Query<(&mut Transform, &mut DirectionalLight), With<Sun>>,
+) {
+    let angle = TAU * (time_of_day.fraction - 0.25);
+    // Slightly tilted so the sun never passes straight overhead
+    let sun_direction = Vec3::new(angle.cos(), angle.sin(), 0.4).normalize();
+    let light = time_of_day.light();
+    sun_query.for_each_mut(|(mut transform, mut directional_light)| {
+        *transform = Transform::IDENTITY.looking_to(-sun_direction, Vec3::Y);
+        directional_light.illuminance = SUN_ILLUMINANCE * light;
+    });
+    if let Some(mut ambient_light) = ambient_light {
+        ambient_light.brightness =
+            NIGHT_AMBIENT_BRIGHTNESS + (DAY_AMBIENT_BRIGHTNESS - NIGHT_AMBIENT_BRIGHTNESS) * light;
+    }
+}
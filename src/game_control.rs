@@ -14,6 +14,9 @@ pub enum GameControl {
     Movement(MovementControl),
     ClickTarget,
     OpenMenu,
+    SaveGame,
+    LoadGame,
+    SelectSaveSlot(u8),
 }
 
 impl GameControl {
@@ -26,6 +29,11 @@ impl GameControl {
             KeyCode::Space => Some(GameControl::Movement(MovementControl::Up)),
             KeyCode::C => Some(GameControl::Movement(MovementControl::Down)),
             KeyCode::Escape => Some(GameControl::OpenMenu),
+            KeyCode::F5 => Some(GameControl::SaveGame),
+            KeyCode::F9 => Some(GameControl::LoadGame),
+            KeyCode::Key1 => Some(GameControl::SelectSaveSlot(0)),
+            KeyCode::Key2 => Some(GameControl::SelectSaveSlot(1)),
+            KeyCode::Key3 => Some(GameControl::SelectSaveSlot(2)),
             _ => None,
         }
     }
@@ -0,0 +1,183 @@
+use crate::block_types::BlockType;
+use crate::core_components::{HasPosition, Moisture, Species};
+use crate::day_night::DayNightPlugin;
+use crate::hydrology::assign_moisture;
+use crate::lifecycles::{LifeCycleSet, LifeCyclesPlugin};
+use crate::organism_index::OrganismIndex;
+use crate::sim_rng::{RngStream, SimRng, SimRngPlugin, WorldSeed};
+use crate::soil::SoilPlugin;
+use crate::species::SpeciesRegistry;
+use crate::trees::{grow_trees, TreeQuery};
+use crate::voxel_access::{VoxelAccess, VoxelGrid};
+use crate::voxel_painting::{apply_voxel_edits, spread_seeds, SetVoxel, SpreadSeeds};
+use crate::weather::WeatherPlugin;
+use crate::world_gen::WorldGenSettings;
+use bevy::prelude::*;
+use bevy_voxel_world::prelude::*;
+use std::path::PathBuf;
+
+/// Runs the organism lifecycles without a window, GPU or audio, e.g.
+/// `App::new().add_plugins((MinimalPlugins, HeadlessSimPlugin::default()))`.
+/// The voxel world is replaced by `HeadlessVoxels`, which starts out empty,
+/// and species are read straight from `species_dir`. Runs with the same
+/// `world_seed` and inputs are identical.
+pub struct HeadlessSimPlugin {
+    pub species_dir: PathBuf,
+    pub world_seed: WorldSeed,
+}
+
+impl Default for HeadlessSimPlugin {
+    fn default() -> Self {
+        HeadlessSimPlugin {
+            species_dir: PathBuf::from("assets/species"),
+            world_seed: WorldSeed(WorldGenSettings::default().seed),
+        }
+    }
+}
+
+impl Plugin for HeadlessSimPlugin {
+    fn build(&self, app: &mut App) {
+        let species_registry = SpeciesRegistry::from_dir(&self.species_dir)
+            .unwrap_or_else(|error| panic!("Couldn't load species: {error}"));
+        app.insert_resource(species_registry)
+            // Gives the `WorldSeed`; otherwise only the sea level is used, as
+            // there is no terrain
+            .insert_resource(WorldGenSettings {
+                seed: self.world_seed.0,
+                ..default()
+            })
+            .init_resource::<HeadlessVoxels>()
+            .add_plugins((
+                SimRngPlugin,
+                LifeCyclesPlugin,
+                DayNightPlugin::default(),
+                WeatherPlugin,
+                SoilPlugin,
+            ))
+            .add_systems(
+                Update,
+                (
+                    headless_spread_seeds_system,
+                    headless_grow_trees_system,
+                    headless_assign_moisture_system,
+                    headless_voxel_edits_system,
+                )
+                    .chain()
+                    .after(LifeCycleSet),
+            );
+    }
+}
+
+/// In-memory stand-in for the voxel world
+#[derive(Resource, Default, Clone, Debug, Deref, DerefMut)]
+pub struct HeadlessVoxels(pub VoxelGrid);
+
+fn headless_voxel_edits_system(
+    mut voxels: ResMut<HeadlessVoxels>,
+    mut voxel_edits: EventReader<SetVoxel>,
+) {
+    apply_voxel_edits(&mut voxels.0, voxel_edits.read().cloned());
+}
+
+fn headless_spread_seeds_system(
+    mut commands: Commands,
+    mut index: ResMut<OrganismIndex>,
+    voxels: Res<HeadlessVoxels>,
+    mut sim_rng: ResMut<SimRng>,
+    mut seed_spreads: EventReader<SpreadSeeds>,
+) {
+    let rng = sim_rng.stream(RngStream::SeedSpreading);
+    seed_spreads
+        .read()
+        .for_each(|spread| spread_seeds(&mut commands, &mut index, &voxels.0, spread, rng));
+}
+
+fn headless_grow_trees_system(
+    mut commands: Commands,
+    voxels: Res<HeadlessVoxels>,
+    index: Res<OrganismIndex>,
+    species_registry: Res<SpeciesRegistry>,
+    mut voxel_edits: EventWriter<SetVoxel>,
+    mut trees: TreeQuery,
+) {
+    grow_trees(
+        &mut commands,
+        &voxels.0,
+        &index,
+        &species_registry,
+        &mut voxel_edits,
+        &mut trees,
+    );
+}
+
+fn headless_assign_moisture_system(
+    mut commands: Commands,
+    voxels: Res<HeadlessVoxels>,
+    settings: Res<WorldGenSettings>,
+    query: Query<(Entity, &HasPosition), (With<Species>, Without<Moisture>)>,
+) {
+    let water = WorldVoxel::Solid(BlockType::Water.index());
+    let is_water = |x: i32, z: i32| {
+        voxels
+            .get_surface_voxel_at_2d_pos(Vec2::new(x as f32, z as f32))
+            .is_some_and(|(_, voxel)| voxel == water)
+    };
+    assign_moisture(&mut commands, is_water, settings.sea_level, &query);
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::block_types::BlockType;
+    use crate::core_components::*;
+    use crate::day_night::TimeOfDay;
+    use crate::headless::*;
+    use crate::spawner::spawn_organism;
+    use crate::voxel_access::VoxelAccess;
+    use crate::weather::{Weather, WeatherKind};
+    use bevy::ecs::system::CommandQueue;
+    use bevy::time::TimeUpdateStrategy;
+    use bevy_voxel_world::prelude::*;
+    use std::time::Duration;
+
+    #[test]
+    fn wheat_is_harvested_without_a_window() {
+        let mut app = App::new();
+        app.add_plugins((MinimalPlugins, HeadlessSimPlugin::default()))
+            // Always a clear noon, so growth isn't stalled by the night or a drought
+            .insert_resource(TimeOfDay {
+                fraction: 0.5,
+                day_length: 0.,
+            })
+            .insert_resource(Weather::new(WeatherKind::Clear, f32::INFINITY))
+            .insert_resource(TimeUpdateStrategy::ManualDuration(Duration::from_millis(
+                250,
+            )));
+
+        let dirt = WorldVoxel::Solid(BlockType::Dirt.index());
+        let mut voxels = app.world.resource_mut::<HeadlessVoxels>();
+        (-8..=8).for_each(|x| (-8..=8).for_each(|z| voxels.set_voxel(IVec3::new(x, 0, z), dirt)));
+
+        let wheat = app
+            .world
+            .resource::<SpeciesRegistry>()
+            .by_name("Wheat")
+            .unwrap();
+        let player = app.world.spawn(PlayerInventory::new()).id();
+        let mut queue = CommandQueue::default();
+        spawn_organism(
+            &mut Commands::new(&mut queue, &app.world),
+            wheat,
+            LifePhase::Seed,
+            IVec3::Y,
+            Some(player),
+        );
+        queue.apply(&mut app.world);
+
+        (0..400).for_each(|_| app.update());
+
+        let inventory = app.world.get::<PlayerInventory>(player).unwrap();
+        assert!(inventory
+            .resources
+            .contains_key(&FarmResource::Seeds(wheat)));
+    }
+}
@@ -0,0 +1,128 @@
+use crate::map_setup::{TerrainColumn, TerrainGenerator};
+use crate::organism_index::CHUNK_SIZE;
+use crate::shared_cache::SharedCache;
+use bevy::prelude::*;
+use bevy_voxel_world::prelude::*;
+use std::sync::Arc;
+
+/// Meshing a chunk also looks at the voxels just outside it
+const PADDING: i32 = 1;
+const HEIGHTMAP_SIZE: i32 = CHUNK_SIZE + 2 * PADDING;
+/// Heightmaps are kept this many chunks beyond the spawning distance, so they
+/// aren't thrown away and worked out again as the camera moves back and forth
+const EVICTION_MARGIN: u32 = 2;
+
+/// The `TerrainColumn` of every column of voxels in a column of chunks, and of
+/// the columns just around it
+#[derive(Debug)]
+pub struct Heightmap {
+    min: IVec2,
+    columns: Vec<TerrainColumn>,
+}
+
+impl Heightmap {
+    pub fn new(terrain: &TerrainGenerator, chunk_column: IVec2) -> Self {
+        let min = chunk_column * CHUNK_SIZE - IVec2::splat(PADDING);
+        let waters = terrain.waters_around(min, min + IVec2::splat(HEIGHTMAP_SIZE - 1));
+        let columns = (0..HEIGHTMAP_SIZE)
+            .flat_map(|z| (0..HEIGHTMAP_SIZE).map(move |x| min + IVec2::new(x, z)))
+            .map(|column| terrain.column_among(&waters, column.x, column.y))
+            .collect();
+        Heightmap { min, columns }
+    }
+
+    /// `None` for columns outside the heightmap
+    pub fn get(&self, x: i32, z: i32) -> Option<&TerrainColumn> {
+        let local = IVec2::new(x, z) - self.min;
+        if local.cmplt(IVec2::ZERO).any() || local.cmpge(IVec2::splat(HEIGHTMAP_SIZE)).any() {
+            return None;
+        }
+        self.columns
+            .get((local.x + local.y * HEIGHTMAP_SIZE) as usize)
+    }
+}
+
+/// Heightmaps by chunk column (a chunk key's `x` and `z`), shared by every chunk
+/// stacked in the column so its noise is only sampled once. Chunks are generated
+/// on other threads, hence the lock. Heightmaps far from the camera are evicted
+/// by `evict_heightmaps_system`.
+#[derive(Resource, Clone, Default, Debug)]
+pub struct HeightmapCache(SharedCache<Heightmap>);
+
+impl HeightmapCache {
+    pub fn get_or_insert(&self, terrain: &TerrainGenerator, chunk_column: IVec2) -> Arc<Heightmap> {
+        self.0
+            .get_or_insert_with(chunk_column, || Heightmap::new(terrain, chunk_column))
+    }
+
+    /// The terrain column at `x`, `z`, from the heightmap of the chunk column it is in
+    pub fn column(&self, terrain: &TerrainGenerator, x: i32, z: i32) -> TerrainColumn {
+        let chunk_column = IVec2::new(x, z).div_euclid(IVec2::splat(CHUNK_SIZE));
+        *self
+            .get_or_insert(terrain, chunk_column)
+            .get(x, z)
+            .expect("a column is in its own chunk column's heightmap")
+    }
+
+    /// Drops the heightmaps of chunk columns more than `distance` chunks from `center`
+    pub fn evict_beyond(&self, center: IVec2, distance: u32) {
+        let max_squared = (distance * distance) as i32;
+        self.0
+            .retain(|chunk_column| (chunk_column - center).length_squared() <= max_squared);
+    }
+}
+
+/// Evicts the rivers and lakes the terrain generator has worked out along with
+/// the heightmaps, as they are only needed to work out heightmaps
+pub fn evict_heightmaps_system(
+    cache: Res<HeightmapCache>,
+    terrain: Res<TerrainGenerator>,
+    configuration: Res<VoxelWorldConfiguration>,
+    camera: Query<&GlobalTransform, With<VoxelWorldCamera>>,
+) {
+    let Ok(camera) = camera.get_single() else {
+        return;
+    };
+    let column = camera.translation().xz().floor().as_ivec2();
+    let distance = configuration.spawning_distance + EVICTION_MARGIN;
+    cache.evict_beyond(column.div_euclid(IVec2::splat(CHUNK_SIZE)), distance);
+    terrain.evict_waters_beyond(column, distance as i32 * CHUNK_SIZE);
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::heightmap::*;
+    use crate::world_gen::WorldGenSettings;
+
+    #[test]
+    fn heightmaps_are_shared_until_evicted() {
+        let terrain = TerrainGenerator::new(&WorldGenSettings {
+            seed: 5,
+            ..default()
+        });
+        let cache = HeightmapCache::default();
+        let heightmap = cache.get_or_insert(&terrain, IVec2::new(1, -1));
+        assert!(Arc::ptr_eq(
+            &heightmap,
+            &cache.get_or_insert(&terrain, IVec2::new(1, -1))
+        ));
+        // Including the padding around the chunk column
+        assert_eq!(
+            heightmap.get(CHUNK_SIZE * 2, -CHUNK_SIZE - 1),
+            Some(&terrain.column(CHUNK_SIZE * 2, -CHUNK_SIZE - 1))
+        );
+        assert_eq!(heightmap.get(CHUNK_SIZE * 2 + 1, 0), None);
+
+        let far = cache.get_or_insert(&terrain, IVec2::new(10, 0));
+        cache.evict_beyond(IVec2::ZERO, 5);
+        assert!(Arc::ptr_eq(
+            &heightmap,
+            &cache.get_or_insert(&terrain, IVec2::new(1, -1))
+        ));
+        // Worked out again once evicted
+        assert!(!Arc::ptr_eq(
+            &far,
+            &cache.get_or_insert(&terrain, IVec2::new(10, 0))
+        ));
+    }
+}
@@ -0,0 +1,104 @@
+use crate::core_components::*;
+use crate::heightmap::HeightmapCache;
+use crate::map_setup::TerrainGenerator;
+use crate::world_gen::WorldGenSettings;
+use bevy::prelude::*;
+
+/// Moisture of ground right by the water
+pub const MAX_MOISTURE: u32 = 5;
+/// Moisture of high ground far from any water
+pub const MIN_MOISTURE: u32 = 1;
+/// Columns further than this from water get no moisture from it
+const WATER_SEARCH_RADIUS: i32 = 8;
+/// Ground this far above the sea, or higher, gets no moisture from its height
+const DRY_HEIGHT: i32 = 32;
+/// Low ground is at most this moist from its height alone
+const LOWLAND_MOISTURE: f32 = 0.5;
+
+/// How moist the ground is at `surface`, a surface voxel, from `MIN_MOISTURE` on
+/// dry hilltops up to `MAX_MOISTURE` beside water. Looks for columns where
+/// `is_water` in rings around the column, so this is cheapest near water.
+pub fn moisture(is_water: impl Fn(i32, i32) -> bool, surface: IVec3, sea_level: i32) -> u32 {
+    let water_distance = (0..=WATER_SEARCH_RADIUS).find(|&ring| {
+        (-ring..=ring).any(|offset| {
+            is_water(surface.x + offset, surface.z - ring)
+                || is_water(surface.x + offset, surface.z + ring)
+                || is_water(surface.x - ring, surface.z + offset)
+                || is_water(surface.x + ring, surface.z + offset)
+        })
+    });
+    let from_water = water_distance.map_or(0., |distance| {
+        1. - distance as f32 / (WATER_SEARCH_RADIUS + 1) as f32
+    });
+    let above_sea = (surface.y - sea_level).max(0);
+    let from_height = LOWLAND_MOISTURE * (1. - (above_sea as f32 / DRY_HEIGHT as f32).min(1.));
+    MIN_MOISTURE
+        + ((MAX_MOISTURE - MIN_MOISTURE) as f32 * from_water.max(from_height)).round() as u32
+}
+
+/// Organisms get their `Moisture`, and start out with that much `Water`, once
+/// they have a position. Organisms don't move, so it's only worked out once.
+pub fn assign_moisture(
+    commands: &mut Commands,
+    is_water: impl Fn(i32, i32) -> bool,
+    sea_level: i32,
+    query: &Query<(Entity, &HasPosition), (With<Species>, Without<Moisture>)>,
+) {
+    query.for_each(|(entity, HasPosition { pos })| {
+        let moisture = moisture(&is_water, *pos - IVec3::Y, sea_level);
+        commands
+            .entity(entity)
+            .insert((Moisture(moisture), Water(moisture)));
+    });
+}
+
+/// Water is looked for in the generated terrain rather than the `VoxelWorld`, as
+/// wild organisms are spawned with their chunk, before its voxels are there
+pub fn assign_moisture_system(
+    mut commands: Commands,
+    terrain: Res<TerrainGenerator>,
+    heightmaps: Res<HeightmapCache>,
+    settings: Res<WorldGenSettings>,
+    query: Query<(Entity, &HasPosition), (With<Species>, Without<Moisture>)>,
+) {
+    let is_water = |x, z| terrain.is_under_water(&heightmaps.column(&terrain, x, z));
+    assign_moisture(&mut commands, is_water, settings.sea_level, &query);
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::block_types::BlockType;
+    use crate::hydrology::*;
+    use crate::voxel_access::{VoxelAccess, VoxelGrid};
+    use bevy_voxel_world::prelude::*;
+
+    #[test]
+    fn ground_near_water_is_moister_than_hilltops() {
+        let water = WorldVoxel::Solid(BlockType::Water.index());
+        let dirt = WorldVoxel::Solid(BlockType::Dirt.index());
+        let mut grid = VoxelGrid::default();
+        (-20..=20).for_each(|x| {
+            (-20..=20).for_each(|z| {
+                // A lake around the origin, with a hill far to the east
+                let height = if x > 15 { 40 } else { 2 };
+                let voxel = if x * x + z * z <= 4 { water } else { dirt };
+                grid.set_voxel(IVec3::new(x, height, z), voxel);
+            })
+        });
+        let is_water = |x, z| {
+            grid.get_surface_voxel_at_2d_pos(Vec2::new(x as f32, z as f32))
+                .is_some_and(|(_, voxel)| voxel == water)
+        };
+
+        let lakeside = moisture(is_water, IVec3::new(3, 2, 0), 1);
+        let lowland = moisture(is_water, IVec3::new(-15, 2, 0), 1);
+        let hilltop = moisture(is_water, IVec3::new(18, 40, 0), 1);
+        assert!(lakeside > lowland);
+        assert!(lowland > hilltop);
+        assert_eq!(hilltop, MIN_MOISTURE);
+        assert_eq!(moisture(is_water, IVec3::new(2, 2, 0), 1), MAX_MOISTURE);
+
+        // Height is measured from the sea, wherever it is
+        assert_eq!(moisture(is_water, IVec3::new(18, 40, 0), 40), lowland);
+    }
+}
@@ -2,38 +2,89 @@
 
 mod actions;
 mod audio;
+mod biome;
 mod block_types;
 mod build_common;
 mod camera_handler;
 mod core_components;
+mod day_night;
 mod game_control;
+#[cfg(feature = "headless")]
+mod headless;
+mod heightmap;
+mod hydrology;
 mod lifecycles;
 mod loading;
 mod map_setup;
 mod menu;
+mod organism_index;
 mod player;
+mod region_store;
+mod save_load;
 mod scene_handler;
+mod shared_cache;
+mod sim_rng;
+mod soil;
 mod spawner;
+mod species;
 mod timer;
+mod trees;
 mod ui_handler;
+mod voxel_access;
 mod voxel_painting;
+mod waterways;
+mod weather;
+mod wild_vegetation;
+mod world_gen;
 
 use crate::actions::ActionsPlugin;
 use crate::audio::InternalAudioPlugin;
+use crate::biome::BiomePlugin;
 use crate::camera_handler::CameraHandlerPlugin;
-use crate::lifecycles::LifeCyclesPlugin;
+use crate::day_night::DayNightPlugin;
+use crate::heightmap::evict_heightmaps_system;
+use crate::hydrology::assign_moisture_system;
+use crate::lifecycles::{LifeCycleSet, LifeCyclesPlugin};
 use crate::loading::LoadingPlugin;
-use crate::map_setup::map_setup;
+use crate::map_setup::{defer_chunk_spawning, map_setup};
 use crate::menu::MenuPlugin;
 use crate::player::PlayerPlugin;
+use crate::save_load::SaveLoadPlugin;
 use crate::scene_handler::SceneSwitchPlugin;
+use crate::sim_rng::SimRngPlugin;
+use crate::soil::SoilPlugin;
 use crate::timer::TimerPlugin;
+use crate::trees::grow_trees_system;
 use crate::ui_handler::UiHandlerPlugin;
-use crate::voxel_painting::paint_voxel_system;
+use crate::voxel_painting::{apply_voxel_edits_system, paint_voxel_system, spread_seeds_system};
+use crate::weather::WeatherPlugin;
+use crate::wild_vegetation::WildVegetationPlugin;
 use bevy::app::App;
 #[cfg(debug_assertions)]
 use bevy::diagnostic::{FrameTimeDiagnosticsPlugin, LogDiagnosticsPlugin};
 use bevy::prelude::*;
+// Re-exported below with the headless feature, which would import them twice
+#[cfg(not(feature = "headless"))]
+use crate::{heightmap::HeightmapCache, map_setup::TerrainGenerator, world_gen::WorldGenSettings};
+
+// What balance experiments, integration tests and benchmarks need to drive a headless simulation
+#[cfg(feature = "headless")]
+pub use crate::{
+    block_types::BlockType,
+    core_components::{
+        Dead, FarmResource, Fruiting, Germinated, Growing, LifePhase, Mature, PlayerInventory,
+        Pollinated, Seed, Species,
+    },
+    headless::{HeadlessSimPlugin, HeadlessVoxels},
+    heightmap::HeightmapCache,
+    lifecycles::LifeCycleBudget,
+    map_setup::{get_voxel_fn, TerrainGenerator},
+    sim_rng::WorldSeed,
+    spawner::spawn_organism,
+    species::SpeciesRegistry,
+    voxel_access::{VoxelAccess, VoxelGrid},
+    world_gen::WorldGenSettings,
+};
 
 // This example game uses States to separate logic
 // See https://bevy-cheatbook.github.io/programming/states.html
@@ -53,28 +104,67 @@ pub struct GamePlugin;
 
 impl Plugin for GamePlugin {
     fn build(&self, app: &mut App) {
+        let world_gen_settings = WorldGenSettings::from_args(std::env::args().skip(1))
+            .unwrap_or_else(|error| {
+                eprintln!(
+                    "Invalid command line: {error}\n{}",
+                    WorldGenSettings::usage()
+                );
+                std::process::exit(2);
+            });
         app.add_state::<GameState>()
+            .insert_resource(world_gen_settings)
             .add_plugins((
+                SimRngPlugin,
                 LoadingPlugin,
                 SceneSwitchPlugin,
                 MenuPlugin,
                 ActionsPlugin,
                 InternalAudioPlugin,
                 PlayerPlugin,
-                LifeCyclesPlugin,
                 CameraHandlerPlugin,
                 UiHandlerPlugin,
                 TimerPlugin,
+                SaveLoadPlugin,
+            ))
+            // The simulation's plugins; new ones go in a group of their own, as Bevy
+            // only takes tuples of up to 15 plugins
+            .add_plugins((
+                LifeCyclesPlugin,
+                DayNightPlugin::default(),
+                WeatherPlugin,
+                SoilPlugin,
+                BiomePlugin,
+                WildVegetationPlugin,
             ))
+            .configure_sets(Update, LifeCycleSet.run_if(in_state(GameState::Playing)))
+            // The world is generated once, when it is first played; see `WorldGenSettings`
+            .add_systems(Startup, defer_chunk_spawning)
+            .add_systems(
+                OnEnter(GameState::Playing),
+                map_setup.run_if(not(resource_exists::<TerrainGenerator>())),
+            )
+            .add_systems(
+                Update,
+                (
+                    paint_voxel_system,
+                    spread_seeds_system,
+                    grow_trees_system,
+                    assign_moisture_system.run_if(resource_exists::<TerrainGenerator>()),
+                    apply_voxel_edits_system,
+                )
+                    .chain()
+                    .after(LifeCycleSet)
+                    .run_if(in_state(GameState::Playing)),
+            )
             .add_systems(
                 Update,
-                paint_voxel_system.run_if(in_state(GameState::Playing)),
+                evict_heightmaps_system.run_if(resource_exists::<HeightmapCache>()),
             );
 
         #[cfg(debug_assertions)]
         {
-            app.add_plugins((FrameTimeDiagnosticsPlugin, LogDiagnosticsPlugin::default()))
-                .add_systems(Startup, map_setup);
+            app.add_plugins((FrameTimeDiagnosticsPlugin, LogDiagnosticsPlugin::default()));
         }
     }
 }
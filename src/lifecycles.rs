@@ -1,367 +1,666 @@
+use crate::biome::Biome;
+use crate::block_types::BlockType;
 use crate::core_components::*;
-use crate::spawner::spawn_organism;
-use crate::voxel_painting::{get_growth_voxel, paint_voxel_unchecked};
-use crate::GameState;
+use crate::day_night::TimeOfDay;
+use crate::organism_index::{
+    index_moved_organisms_system, unindex_removed_organisms_system, OrganismIndex,
+};
+use crate::sim_rng::{RngStream, SimRng};
+use crate::soil::SoilFertility;
+use crate::species::{Pollination, SpeciesDef, SpeciesRegistry};
+use crate::trees::clear_tree;
+use crate::voxel_painting::{clear_voxel, paint_voxel_unchecked, SetVoxel, SpreadSeeds};
+use crate::weather::Weather;
 use bevy::prelude::*;
 use bevy::utils::HashMap;
-use bevy_voxel_world::prelude::*;
-use itertools::Itertools;
-use std::collections::HashSet;
-use std::iter;
+use std::collections::VecDeque;
 
-const SYSTEM_PROCESSING_CAP: usize = 1024;
+/// Organisms each lifecycle system processes per frame, unless changed with `LifeCycleBudget`
+const DEFAULT_ORGANISMS_PER_FRAME: usize = 1024;
+/// Seconds a cross-pollinating organism waits before looking for a partner again
+const POLLINATION_RETRY: f32 = 10.;
+/// Slow enough that a healthy plant survives a night without light
+const HEALTH_LOSS_PER_SECOND: f32 = 0.5;
+const HEALTH_RECOVERY_PER_SECOND: f32 = 2.;
+
+/// How many organisms each lifecycle system processes per frame. Once a farm
+/// outgrows it, the organisms are processed in turns; see `Rotation`.
+#[derive(Resource, Clone, Copy, Debug)]
+pub struct LifeCycleBudget {
+    pub organisms_per_frame: usize,
+}
+
+impl Default for LifeCycleBudget {
+    fn default() -> Self {
+        LifeCycleBudget {
+            organisms_per_frame: DEFAULT_ORGANISMS_PER_FRAME,
+        }
+    }
+}
+
+/// Gives the organisms a system handles their turns, at most the budget each frame.
+/// A cursor works through the organisms there were when it last started over,
+/// and starts over once it reaches the end, so every organism gets a turn before
+/// any gets a second one. Organisms spawned in the meantime join on the next pass,
+/// and those that have left the system's query are skipped. Organisms catch up on
+/// the time they waited through `LastProcessed`.
+#[derive(Default)]
+pub struct Rotation {
+    pending: VecDeque<Entity>,
+}
+
+impl Rotation {
+    /// The organisms whose turn it is. `organisms` lists all the system's
+    /// organisms, and is only called when the rotation starts over.
+    pub fn next_turn<I: IntoIterator<Item = Entity>>(
+        &mut self,
+        budget: &LifeCycleBudget,
+        organisms: impl FnOnce() -> I,
+    ) -> Vec<Entity> {
+        if self.pending.is_empty() {
+            self.pending.extend(organisms());
+        }
+        let turn = budget.organisms_per_frame.max(1).min(self.pending.len());
+        self.pending.drain(..turn).collect()
+    }
+}
 
 pub fn seed_to_germinate_system(
     time: Res<Time>,
+    budget: Res<LifeCycleBudget>,
+    mut rotation: Local<Rotation>,
+    species_registry: Res<SpeciesRegistry>,
     mut commands: Commands,
-    mut voxel_world: VoxelWorld,
-    mut query: Query<(
-        Entity,
-        &HasPosition,
-        &Species,
-        &LifePhase,
-        &mut GerminationTimer,
-        &Water,
-        &Soil,
-    )>,
+    mut voxel_edits: EventWriter<SetVoxel>,
+    mut query: Query<
+        (
+            Entity,
+            &HasPosition,
+            &Species,
+            &mut GerminationTimer,
+            &Water,
+            &Soil,
+            Option<&Biome>,
+            &mut LastProcessed,
+        ),
+        With<Seed>,
+    >,
 ) {
-    query
-        .iter_mut()
-        .filter(|(_, _, _, life_phase, _, _, _)| **life_phase == LifePhase::Seed)
-        .take(SYSTEM_PROCESSING_CAP)
-        .for_each(
-            |(entity, HasPosition { pos }, species, _life_phase, mut timer, water, soil)| {
-                let needs = species.germination_needs();
-
-                timer.0 -= time.delta_seconds();
-
-                if timer.0 <= 0.0 && water.0 >= needs.water.0 && soil.0 >= needs.soil.0 {
-                    // If conditions are met, transition from Seed to Germinated
-                    commands
-                        .entity(entity)
-                        .remove::<LifePhase>()
-                        .remove::<GerminationTimer>()
-                        // We just add a time delay to go from Germinated to Growing
-                        .insert(GerminationTimer(0.0))
-                        .insert(LifePhase::Germinated {/* ... */});
-                    paint_voxel_unchecked(
-                        &mut voxel_world,
-                        *pos,
-                        species.block_type(&LifePhase::Germinated),
-                    );
-                }
-            },
-        )
+    rotation
+        .next_turn(&budget, || query.iter().map(|item| item.0))
+        .into_iter()
+        .for_each(|organism| {
+            let Ok((
+                entity,
+                HasPosition { pos },
+                species,
+                mut timer,
+                water,
+                soil,
+                biome,
+                mut last_processed,
+            )) = query.get_mut(organism)
+            else {
+                return;
+            };
+            let species_def = species_registry.get(*species);
+            let needs = &species_def.germination_needs;
+
+            // Seeds lie dormant in biomes their species won't grow in at all
+            let preference = biome.map_or(1., |biome| species_def.biome_preference(*biome));
+            timer.0 -= last_processed.advance(&time) * preference;
+
+            if timer.0 <= 0.0 && water.0 >= needs.water.0 && soil.0 >= needs.soil.0 {
+                // If conditions are met, transition from Seed to Germinated
+                commands
+                    .entity(entity)
+                    .remove::<(Seed, GerminationTimer)>()
+                    // We just add a time delay to go from Germinated to Growing
+                    .insert((GerminationTimer(0.0), Germinated));
+                paint_voxel_unchecked(
+                    &mut voxel_edits,
+                    *pos,
+                    species_def.block_type(&LifePhase::Germinated),
+                );
+            }
+        })
 }
 
 /// We just add a time delay to go from Germinated to Growing
 pub fn growth_system(
     time: Res<Time>,
-    mut voxel_world: VoxelWorld,
+    budget: Res<LifeCycleBudget>,
+    mut rotation: Local<Rotation>,
+    species_registry: Res<SpeciesRegistry>,
+    mut voxel_edits: EventWriter<SetVoxel>,
     mut commands: Commands,
-    mut query: Query<(
-        Entity,
-        &HasPosition,
-        &Species,
-        &LifePhase,
-        &mut GerminationTimer,
-    )>,
+    mut query: Query<
+        (
+            Entity,
+            &HasPosition,
+            &Species,
+            &mut GerminationTimer,
+            &mut LastProcessed,
+        ),
+        With<Germinated>,
+    >,
 ) {
-    query
-        .iter_mut()
-        .filter(|(_, _, _, life_phase, _)| **life_phase == LifePhase::Germinated)
-        .take(SYSTEM_PROCESSING_CAP)
-        .for_each(
-            |(entity, HasPosition { pos }, species, _life_phase, mut timer)| {
-                timer.0 -= time.delta_seconds();
-
-                if timer.0 <= 0.0 {
-                    let phase = LifePhase::Growing {
-                        needs: species.growing_needs(),
-                    };
-                    paint_voxel_unchecked(&mut voxel_world, *pos, species.block_type(&phase));
-                    commands.entity(entity).remove::<LifePhase>().insert(phase);
-                }
-            },
-        )
+    rotation
+        .next_turn(&budget, || query.iter().map(|item| item.0))
+        .into_iter()
+        .for_each(|organism| {
+            let Ok((entity, HasPosition { pos }, species, mut timer, mut last_processed)) =
+                query.get_mut(organism)
+            else {
+                return;
+            };
+            timer.0 -= last_processed.advance(&time);
+
+            if timer.0 <= 0.0 {
+                let species_def = species_registry.get(*species);
+                paint_voxel_unchecked(&mut voxel_edits, *pos, species_def.blocks.growing);
+                commands
+                    .entity(entity)
+                    .remove::<Germinated>()
+                    .insert(Growing {
+                        needs: species_def.growing_needs.clone(),
+                        drawn: 0.,
+                    });
+            }
+        })
 }
 
+#[allow(clippy::too_many_arguments)]
 pub fn maturation_system(
     time: Res<Time>,
-    mut voxel_world: VoxelWorld,
+    budget: Res<LifeCycleBudget>,
+    mut rotation: Local<Rotation>,
+    time_of_day: Res<TimeOfDay>,
+    weather: Res<Weather>,
+    species_registry: Res<SpeciesRegistry>,
+    mut fertility: ResMut<SoilFertility>,
+    mut voxel_edits: EventWriter<SetVoxel>,
     mut commands: Commands,
     mut query: Query<(
         Entity,
         &HasPosition,
         &Species,
-        &mut LifePhase,
+        &mut Growing,
         &Water,
-        &Soil,
+        &mut Soil,
+        &mut Health,
+        &mut LastProcessed,
     )>,
 ) {
-    let light = 1.0; // TODO: adjust based on weather
-    query
-        .iter_mut()
-        .filter(|(_, _, _, life_phase, _, _)| {
-            matches!(**life_phase, LifePhase::Growing { needs: _ })
-        })
-        .take(SYSTEM_PROCESSING_CAP)
-        .for_each(
-            |(entity, HasPosition { pos }, species, mut life_phase, water, soil)| {
-                if let LifePhase::Growing { mut needs } = life_phase.clone() {
-                    // Check there are some non-zero conditions for growth
-                    if needs.time <= 0.0
-                        && needs.light <= 0.0
-                        && *needs.water == 0
-                        && *needs.soil == 0
-                    {
-                        paint_voxel_unchecked(
-                            &mut voxel_world,
-                            *pos,
-                            species.block_type(&LifePhase::Mature),
-                        );
-                        commands
-                            .entity(entity)
-                            .remove::<LifePhase>()
-                            .insert(LifePhase::Mature)
-                            .insert(MatureAgeTimer(0.));
-                    } else if water.0 > 0 && soil.0 > 0 && light > 0.01 {
-                        // Get rate-limiting resource value
-                        // (for now we assume 1:1 usage between each)
-                        let growth_value = f32::min(f32::min(water.0 as f32, soil.0 as f32), light);
-                        if needs.time >= 0. {
-                            needs.time -= time.delta_seconds();
-                        }
-                        if needs.light >= 0. {
-                            needs.light -= light;
-                        }
-                        needs.water = Water(needs.water.saturating_sub(growth_value as u32));
-                        needs.soil = Soil(needs.soil.saturating_sub(growth_value as u32));
-                        *life_phase = LifePhase::Growing { needs };
-                    } else {
-                        // TODO: maybe add some health logic later where entities could lose health
-                        //     : if resources are not enough to maintain life
-                    }
+    let light = time_of_day.light() * weather.kind.light_factor();
+    rotation
+        .next_turn(&budget, || query.iter().map(|item| item.0))
+        .into_iter()
+        .for_each(|organism| {
+            let Ok((
+                entity,
+                HasPosition { pos },
+                species,
+                mut growing,
+                water,
+                mut soil,
+                mut health,
+                mut last_processed,
+            )) = query.get_mut(organism)
+            else {
+                return;
+            };
+            let species_def = species_registry.get(*species);
+            let elapsed = last_processed.advance(&time);
+            let was_wilting = health.is_wilting();
+            soil.0 = fertility.get(pos.xz());
+            let growing = &mut *growing;
+            let needs = &mut growing.needs;
+            // Check there are some non-zero conditions for growth
+            if needs.time <= 0.0 && needs.light <= 0.0 && *needs.water == 0 && *needs.soil == 0 {
+                paint_voxel_unchecked(&mut voxel_edits, *pos, species_def.blocks.mature);
+                commands
+                    .entity(entity)
+                    .remove::<Growing>()
+                    .insert((Mature, MatureAgeTimer(0.)));
+                return;
+            }
+            if can_grow(water, &soil, light) {
+                // Water and soil are drawn 1:1 over the time since the last turn, as
+                // fast as the scarcer of the two allows, and as the light allows
+                let growth_value = water.0.min(soil.0) as f32 * light * elapsed;
+                let rate = water.growth_rate();
+                if needs.time >= 0. {
+                    needs.time -= elapsed * rate;
                 }
-            },
-        )
+                if needs.light >= 0. {
+                    needs.light -= light * elapsed * rate;
+                }
+                draw_water_and_soil(
+                    &mut needs.water,
+                    &mut needs.soil,
+                    &mut growing.drawn,
+                    growth_value,
+                    &mut fertility,
+                    pos.xz(),
+                );
+            }
+            tend_health(&mut health, water, &soil, light, elapsed);
+            update_wilting(
+                &mut commands,
+                &mut voxel_edits,
+                entity,
+                *pos,
+                species_def,
+                species_def.blocks.growing,
+                &health,
+                was_wilting,
+            );
+        })
 }
 
-// TODO: check for polination requirements by searching for all nearby
-//     : surface voxels
-/// We just add a time delay to go from Mature to Pollinated, and check
-/// for pollination requirements.
+/// We add a time delay to go from Mature to Pollinated, and check the species'
+/// pollination requirements once it has passed. Mature plants still need water,
+/// soil and light to stay healthy while they wait.
+#[allow(clippy::too_many_arguments)]
 pub fn pollination_system(
     time: Res<Time>,
-    mut voxel_world: VoxelWorld,
+    budget: Res<LifeCycleBudget>,
+    mut rotation: Local<Rotation>,
+    time_of_day: Res<TimeOfDay>,
+    weather: Res<Weather>,
+    species_registry: Res<SpeciesRegistry>,
+    fertility: Res<SoilFertility>,
+    index: Res<OrganismIndex>,
+    mut voxel_edits: EventWriter<SetVoxel>,
     mut commands: Commands,
-    mut query: Query<(
-        Entity,
-        &HasPosition,
-        &Species,
-        &LifePhase,
-        &mut MatureAgeTimer,
-    )>,
+    mut query: Query<
+        (
+            Entity,
+            &HasPosition,
+            &Species,
+            &mut MatureAgeTimer,
+            Option<&PollinationFailed>,
+            &Water,
+            &mut Health,
+            &mut LastProcessed,
+        ),
+        With<Mature>,
+    >,
+    // Anything in flower or bearing fruit can still pass on its pollen
+    partners: Query<&Species, Or<(With<Mature>, With<Pollinated>, With<Fruiting>)>>,
 ) {
-    query
-        .iter_mut()
-        .filter(|(_, _, _, life_phase, _)| **life_phase == LifePhase::Mature)
-        .take(SYSTEM_PROCESSING_CAP)
-        .for_each(
-            |(entity, HasPosition { pos }, species, _life_phase, mut timer)| {
-                timer.0 -= time.delta_seconds();
-                if timer.0 <= 0.0 {
-                    let phase = LifePhase::Pollinated {
-                        needs: species.fruiting_needs(),
-                    };
-                    paint_voxel_unchecked(&mut voxel_world, *pos, species.block_type(&phase));
-                    commands.entity(entity).remove::<LifePhase>().insert(phase);
+    let has_partner = |entity: Entity, pos: IVec3, species: &Species, radius: u32| {
+        index.in_radius(pos, radius).any(|(_, other)| {
+            other != entity && partners.get(other).is_ok_and(|other| other == species)
+        })
+    };
+
+    let light = time_of_day.light() * weather.kind.light_factor();
+    rotation
+        .next_turn(&budget, || query.iter().map(|item| item.0))
+        .into_iter()
+        .for_each(|organism| {
+            let Ok((
+                entity,
+                HasPosition { pos },
+                species,
+                mut timer,
+                failed,
+                water,
+                mut health,
+                mut last_processed,
+            )) = query.get_mut(organism)
+            else {
+                return;
+            };
+            let species_def = species_registry.get(*species);
+            let elapsed = last_processed.advance(&time);
+            let was_wilting = health.is_wilting();
+            let soil = Soil(fertility.get(pos.xz()));
+            tend_health(&mut health, water, &soil, light, elapsed);
+            update_wilting(
+                &mut commands,
+                &mut voxel_edits,
+                entity,
+                *pos,
+                species_def,
+                species_def.blocks.mature,
+                &health,
+                was_wilting,
+            );
+            if health.is_dead() {
+                return;
+            }
+            timer.0 -= elapsed;
+            if timer.0 <= 0.0 {
+                let pollinated = match species_def.pollination {
+                    Pollination::SelfPollinating => true,
+                    Pollination::Cross { radius } => has_partner(entity, *pos, species, radius),
+                };
+                if pollinated {
+                    paint_voxel_unchecked(&mut voxel_edits, *pos, species_def.blocks.pollinated);
+                    commands
+                        .entity(entity)
+                        .remove::<(Mature, PollinationFailed)>()
+                        .insert(Pollinated {
+                            needs: species_def.fruiting_needs.clone(),
+                            drawn: 0.,
+                        });
+                } else {
+                    timer.0 = POLLINATION_RETRY;
+                    commands.entity(entity).insert(PollinationFailed {
+                        attempts: failed.map_or(0, |failed| failed.attempts) + 1,
+                    });
                 }
-            },
-        )
+            }
+        })
 }
 
 // Similar to the maturation_system
+#[allow(clippy::too_many_arguments)]
 pub fn fruiting_system(
     time: Res<Time>,
-    mut voxel_world: VoxelWorld,
+    budget: Res<LifeCycleBudget>,
+    mut rotation: Local<Rotation>,
+    time_of_day: Res<TimeOfDay>,
+    weather: Res<Weather>,
+    species_registry: Res<SpeciesRegistry>,
+    mut fertility: ResMut<SoilFertility>,
+    mut voxel_edits: EventWriter<SetVoxel>,
     mut commands: Commands,
     mut query: Query<(
         Entity,
         &HasPosition,
         &Species,
-        &mut LifePhase,
+        &mut Pollinated,
         &Water,
-        &Soil,
+        &mut Soil,
         &mut Generations,
+        &mut Health,
+        &mut LastProcessed,
     )>,
 ) {
-    let light = 1.0; // TODO: adjust based on weather
-    query
-        .iter_mut()
-        .filter(|(_, _, _, life_phase, _, _, _)| {
-            matches!(**life_phase, LifePhase::Pollinated { needs: _ })
-        })
-        .take(SYSTEM_PROCESSING_CAP)
-        .for_each(
-            |(
+    let light = time_of_day.light() * weather.kind.light_factor();
+    rotation
+        .next_turn(&budget, || query.iter().map(|item| item.0))
+        .into_iter()
+        .for_each(|organism| {
+            let Ok((
                 entity,
                 HasPosition { pos },
                 species,
-                mut life_phase,
+                mut pollinated,
                 water,
-                soil,
+                mut soil,
                 mut generations,
-            )| {
-                if let LifePhase::Pollinated { mut needs } = life_phase.clone() {
-                    // Check there are some non-zero conditions for growth
-                    if needs.time <= 0.0
-                        && needs.light <= 0.0
-                        && *needs.water == 0
-                        && *needs.soil == 0
-                    {
-                        paint_voxel_unchecked(
-                            &mut voxel_world,
-                            *pos,
-                            species.block_type(&LifePhase::Mature),
-                        );
-                        generations.0 = generations.0.saturating_add(1);
-                        commands
-                            .entity(entity)
-                            .remove::<LifePhase>()
-                            .insert(LifePhase::Fruiting);
-                    } else if water.0 > 0 && soil.0 > 0 && light > 0.01 {
-                        // Get rate-limiting resource value
-                        // (for now we assume 1:1 usage between each)
-                        let growth_value = f32::min(f32::min(water.0 as f32, soil.0 as f32), light);
-                        if needs.time >= 0. {
-                            needs.time -= time.delta_seconds();
-                        }
-                        if needs.light >= 0. {
-                            needs.light -= light;
-                        }
-                        needs.water = Water(needs.water.saturating_sub(growth_value as u32));
-                        needs.soil = Soil(needs.soil.saturating_sub(growth_value as u32));
-                        *life_phase = LifePhase::Pollinated { needs };
-                    } else {
-                        // TODO: maybe add some health logic later where entities could lose health
-                        //     : if resources are not enough to maintain life
-                    }
+                mut health,
+                mut last_processed,
+            )) = query.get_mut(organism)
+            else {
+                return;
+            };
+            let species_def = species_registry.get(*species);
+            let elapsed = last_processed.advance(&time);
+            let was_wilting = health.is_wilting();
+            soil.0 = fertility.get(pos.xz());
+            let pollinated = &mut *pollinated;
+            let needs = &mut pollinated.needs;
+            // Check there are some non-zero conditions for growth
+            if needs.time <= 0.0 && needs.light <= 0.0 && *needs.water == 0 && *needs.soil == 0 {
+                paint_voxel_unchecked(&mut voxel_edits, *pos, species_def.blocks.mature);
+                generations.0 = generations.0.saturating_add(1);
+                commands
+                    .entity(entity)
+                    .remove::<Pollinated>()
+                    .insert(Fruiting);
+                return;
+            }
+            if can_grow(water, &soil, light) {
+                // Water and soil are drawn 1:1 over the time since the last turn, as
+                // fast as the scarcer of the two allows, and as the light allows
+                let growth_value = water.0.min(soil.0) as f32 * light * elapsed;
+                let rate = water.growth_rate();
+                if needs.time >= 0. {
+                    needs.time -= elapsed * rate;
                 }
-            },
-        )
+                if needs.light >= 0. {
+                    needs.light -= light * elapsed * rate;
+                }
+                draw_water_and_soil(
+                    &mut needs.water,
+                    &mut needs.soil,
+                    &mut pollinated.drawn,
+                    growth_value,
+                    &mut fertility,
+                    pos.xz(),
+                );
+            }
+            tend_health(&mut health, water, &soil, light, elapsed);
+            update_wilting(
+                &mut commands,
+                &mut voxel_edits,
+                entity,
+                *pos,
+                species_def,
+                species_def.blocks.pollinated,
+                &health,
+                was_wilting,
+            );
+        })
+}
+
+/// Plants only grow, and stay healthy, with water, soil and light
+fn can_grow(water: &Water, soil: &Soil, light: f32) -> bool {
+    water.0 > 0 && soil.0 > 0 && light > 0.01
+}
+
+/// Plants recover while they can grow, and otherwise lose health
+fn tend_health(health: &mut Health, water: &Water, soil: &Soil, light: f32, elapsed: f32) {
+    if can_grow(water, soil, light) {
+        health.change(HEALTH_RECOVERY_PER_SECOND * elapsed);
+    } else {
+        health.change(-HEALTH_LOSS_PER_SECOND * elapsed);
+    }
 }
 
-/// Either sends the plant to death or back to mature. The player may gain resources
+/// Draws `amount` towards the water and soil needs, the soil from the ground's
+/// fertility. Needs are met in whole units, so the rest is kept in `drawn` for
+/// the organism's next turn.
+fn draw_water_and_soil(
+    water_need: &mut Water,
+    soil_need: &mut Soil,
+    drawn: &mut f32,
+    amount: f32,
+    fertility: &mut SoilFertility,
+    column: IVec2,
+) {
+    *drawn += amount;
+    let units = drawn.floor();
+    *drawn -= units;
+    let units = units as u32;
+    water_need.0 = water_need.0.saturating_sub(units);
+    soil_need.0 -= fertility.take(column, units.min(soil_need.0));
+}
+
+/// Draws a lifespan from the species' distribution for each newly spawned organism
+pub fn assign_lifespan_system(
+    species_registry: Res<SpeciesRegistry>,
+    mut sim_rng: ResMut<SimRng>,
+    mut commands: Commands,
+    query: Query<(Entity, &Species), Without<Lifespan>>,
+) {
+    let rng = sim_rng.stream(RngStream::Lifespans);
+    query.for_each(|(entity, species)| {
+        let lifespan = species_registry.get(*species).sample_lifespan(rng);
+        commands.entity(entity).insert(lifespan);
+    })
+}
+
+/// Either sends the plant to death or back to mature. The player may gain resources,
+/// fewer of them the more the plant's soil is depleted. Plants are only `Fruiting`
+/// until their turn here, so their health isn't updated in it.
+#[allow(clippy::too_many_arguments)]
 pub fn lifecycle_system(
-    mut voxel_world: VoxelWorld,
+    budget: Res<LifeCycleBudget>,
+    mut rotation: Local<Rotation>,
+    species_registry: Res<SpeciesRegistry>,
+    fertility: Res<SoilFertility>,
+    mut voxel_edits: EventWriter<SetVoxel>,
+    mut seed_spreads: EventWriter<SpreadSeeds>,
     mut commands: Commands,
-    mut query: Query<(
-        Entity,
-        &HasPosition,
-        &Species,
-        &LifePhase,
-        &Generations,
-        Option<&OwnedBy>,
-    )>,
+    query: Query<
+        (
+            Entity,
+            &HasPosition,
+            &Species,
+            &Generations,
+            &Lifespan,
+            Option<&OwnedBy>,
+        ),
+        With<Fruiting>,
+    >,
 ) {
-    query
-        .iter_mut()
-        .filter(|(_, _, _, life_phase, _, _)| **life_phase == LifePhase::Fruiting)
-        .take(SYSTEM_PROCESSING_CAP)
-        .for_each(
-            |(entity, HasPosition { pos }, species, _life_phase, generations, owned_by)| {
-                let owner_opt = if let Some(OwnedBy { owner }) = owned_by {
-                    let collect_resource = CollectResource {
-                        owner: *owner,
-                        resource: vec![
-                            (
-                                FarmResource::Seeds(species.clone()),
-                                species.seeds_per_generation(),
-                            ),
-                            (
-                                FarmResource::FoodValue(species.clone()),
-                                species.food_value(),
-                            ),
-                        ]
-                        .into_iter()
-                        .collect(),
-                    };
-                    commands.spawn(collect_resource);
-                    Some(owner)
-                } else {
-                    None
+    rotation
+        .next_turn(&budget, || query.iter().map(|item| item.0))
+        .into_iter()
+        .for_each(|organism| {
+            let Ok((entity, HasPosition { pos }, species, generations, lifespan, owned_by)) =
+                query.get(organism)
+            else {
+                return;
+            };
+            let species_def = species_registry.get(*species);
+            let yield_factor = fertility.yield_factor(pos.xz());
+            let scaled = |amount: u32| (amount as f32 * yield_factor).round() as u32;
+            let owner_opt = if let Some(OwnedBy { owner }) = owned_by {
+                let collect_resource = CollectResource {
+                    owner: *owner,
+                    resource: vec![
+                        (
+                            FarmResource::Seeds(*species),
+                            scaled(species_def.seeds_per_generation),
+                        ),
+                        (
+                            FarmResource::FoodValue(*species),
+                            scaled(species_def.food_value),
+                        ),
+                    ]
+                    .into_iter()
+                    .collect(),
                 };
-                let new_plants = species.spread_per_fruiting();
-                if new_plants > 0 {
-                    let surface_blocks = get_random_surface_voxels(
-                        &voxel_world,
-                        *pos,
-                        species.spread_distance(),
-                        new_plants,
-                    );
-                    surface_blocks
-                        .into_iter()
-                        .filter_map(|(pos, voxel)| get_growth_voxel(&voxel_world, voxel, pos))
-                        .for_each(|growth_pos| {
-                            spawn_organism(
-                                &mut commands,
-                                species.clone(),
-                                crate::core_components::LifePhase::Seed,
-                                growth_pos,
-                                owner_opt.cloned(),
-                            );
-                        })
-                }
+                commands.spawn(collect_resource);
+                Some(owner)
+            } else {
+                None
+            };
+            let new_plants = scaled(species_def.spread_per_fruiting.into()) as u8;
+            if new_plants > 0 {
+                seed_spreads.send(SpreadSeeds {
+                    species: *species,
+                    origin: *pos,
+                    distance: species_def.spread_distance,
+                    count: new_plants,
+                    owner: owner_opt.copied(),
+                });
+            }
 
-                // TODO: for now, we just use the avg generations - introduce randomness
-                let generation_lifetime = species
-                    .min_generations()
-                    .saturating_add(species.max_generations())
-                    / 2;
-                if generations.0 > generation_lifetime {
-                    paint_voxel_unchecked(
-                        &mut voxel_world,
-                        *pos,
-                        species.block_type(&LifePhase::Death),
-                    );
-                    commands
-                        .entity(entity)
-                        .remove::<LifePhase>()
-                        .insert(LifePhase::Death)
-                        .insert(DecayTimer(species.decay_time()));
-                } else {
-                    paint_voxel_unchecked(
-                        &mut voxel_world,
-                        *pos,
-                        species.block_type(&LifePhase::Mature),
-                    );
-                    commands
-                        .entity(entity)
-                        .remove::<LifePhase>()
-                        .insert(LifePhase::Mature);
+            if generations.0 > lifespan.0 {
+                // The crop grew on topsoil, so that is what gets replaced
+                if let (Some(ground), Some(_)) = (species_def.harvested_ground, owner_opt) {
+                    paint_voxel_unchecked(&mut voxel_edits, *pos - IVec3::Y, ground);
                 }
-            },
-        )
+                kill_organism(&mut commands, &mut voxel_edits, entity, *pos, species_def);
+            } else {
+                paint_voxel_unchecked(&mut voxel_edits, *pos, species_def.blocks.mature);
+                commands.entity(entity).remove::<Fruiting>().insert(Mature);
+            }
+        })
+}
+
+/// Paints the death block, or clears the voxel if there is none, and leaves the
+/// organism to decay
+fn kill_organism(
+    commands: &mut Commands,
+    voxel_edits: &mut EventWriter<SetVoxel>,
+    entity: Entity,
+    pos: IVec3,
+    species_def: &SpeciesDef,
+) {
+    match species_def.blocks.death {
+        BlockType::Nothing => clear_voxel(voxel_edits, pos),
+        death => paint_voxel_unchecked(voxel_edits, pos, death),
+    }
+    let mut entity_commands = commands.entity(entity);
+    LifePhase::Death.insert_into(&mut entity_commands);
+    entity_commands.insert(DecayTimer(species_def.decay_time));
 }
 
+/// Starves the organism once its health runs out, otherwise repaints it
+/// when it starts or stops wilting
+#[allow(clippy::too_many_arguments)]
+fn update_wilting(
+    commands: &mut Commands,
+    voxel_edits: &mut EventWriter<SetVoxel>,
+    entity: Entity,
+    pos: IVec3,
+    species_def: &SpeciesDef,
+    phase_block: BlockType,
+    health: &Health,
+    was_wilting: bool,
+) {
+    if health.is_dead() {
+        debug!("{} at {} starved", species_def.name, pos);
+        kill_organism(commands, voxel_edits, entity, pos, species_def);
+    } else if health.is_wilting() && !was_wilting {
+        paint_voxel_unchecked(voxel_edits, pos, BlockType::Wilting);
+    } else if !health.is_wilting() && was_wilting {
+        paint_voxel_unchecked(voxel_edits, pos, phase_block);
+    }
+}
+
+/// Once decayed, the organism and everything it grew is removed, and it gives
+/// back some of the soil it took
+#[allow(clippy::too_many_arguments)]
 pub fn decay_system(
     time: Res<Time>,
-    mut voxel_world: VoxelWorld,
+    budget: Res<LifeCycleBudget>,
+    mut rotation: Local<Rotation>,
+    species_registry: Res<SpeciesRegistry>,
+    mut fertility: ResMut<SoilFertility>,
+    mut voxel_edits: EventWriter<SetVoxel>,
     mut commands: Commands,
-    mut query: Query<(Entity, &HasPosition, &mut DecayTimer)>,
+    mut query: Query<
+        (
+            Entity,
+            &HasPosition,
+            &Species,
+            &mut DecayTimer,
+            &mut LastProcessed,
+            Option<&TreeVoxels>,
+        ),
+        With<Dead>,
+    >,
 ) {
-    query.iter_mut().take(SYSTEM_PROCESSING_CAP).for_each(
-        |(entity, HasPosition { pos }, mut timer)| {
-            timer.0 -= time.delta_seconds();
+    rotation
+        .next_turn(&budget, || query.iter().map(|item| item.0))
+        .into_iter()
+        .for_each(|organism| {
+            let Ok((entity, HasPosition { pos }, species, mut timer, mut last_processed, tree)) =
+                query.get_mut(organism)
+            else {
+                return;
+            };
+            timer.0 -= last_processed.advance(&time);
             if timer.0 <= 0. {
-                // TODO: look into Unset vs Air; we depend on Air but does
-                //     : Unset just change back to the original block type?
-                voxel_world.set_voxel(*pos, WorldVoxel::Air);
+                fertility.give(pos.xz(), species_registry.get(*species).decay_fertility());
+                if let Some(tree) = tree {
+                    clear_tree(&mut voxel_edits, tree);
+                }
+                clear_voxel(&mut voxel_edits, *pos);
                 commands.entity(entity).despawn();
             }
-        },
-    )
+        })
 }
 
 pub fn resource_collection_system(
@@ -372,7 +671,6 @@ pub fn resource_collection_system(
     if !resource_query.is_empty() {
         let mut player_new_resources: HashMap<Entity, CollectResource> = resource_query
             .into_iter()
-            .take(SYSTEM_PROCESSING_CAP)
             .fold(HashMap::new(), |mut acc_map, (_, collect)| {
                 let new_collect = acc_map
                     .remove(&collect.owner)
@@ -392,77 +690,349 @@ pub fn resource_collection_system(
     }
 }
 
-// TODO: needs to actually relate to chunks and blocks
-pub fn spawn_life_in_chunk(
-    commands: &mut Commands,
-    species: Species,
-    phase: LifePhase,
-    count: u16,
-) {
-    (1..=count).for_each(|_| {
-        commands.spawn((species.clone(), phase.clone()));
-    })
-}
-
-// TODO: needs to actually relate to chunks and blocks
-fn init_life(mut commands: Commands) {
-    let init_species: HashSet<Species> = vec![Species::Apple, Species::Wheat].into_iter().collect();
-    init_species.into_iter().for_each(|species| {
-        spawn_life_in_chunk(
-            &mut commands,
-            Species::Apple,
-            LifePhase::Seed,
-            species.wild_organisms_per_chunk(),
-        )
-    })
-}
+/// The organism lifecycle systems. These only touch the voxel world through
+/// `SetVoxel` and `SpreadSeeds` events, so they can run without rendering.
+#[derive(SystemSet, Debug, Hash, PartialEq, Eq, Clone)]
+pub struct LifeCycleSet;
 
 pub struct LifeCyclesPlugin;
 
 impl Plugin for LifeCyclesPlugin {
     fn build(&self, app: &mut App) {
-        app.add_systems(
-            Update,
-            seed_to_germinate_system.run_if(in_state(GameState::Playing)),
-        )
-        .add_systems(Update, growth_system.run_if(in_state(GameState::Playing)))
-        .add_systems(
-            Update,
-            maturation_system.run_if(in_state(GameState::Playing)),
-        )
-        .add_systems(
-            Update,
-            pollination_system.run_if(in_state(GameState::Playing)),
-        )
-        .add_systems(Update, fruiting_system.run_if(in_state(GameState::Playing)))
-        .add_systems(
-            Update,
-            lifecycle_system.run_if(in_state(GameState::Playing)),
-        )
-        .add_systems(Update, decay_system.run_if(in_state(GameState::Playing)))
-        .add_systems(
-            Update,
-            resource_collection_system.run_if(in_state(GameState::Playing)),
-        )
-        .add_systems(Startup, init_life);
+        app.init_resource::<LifeCycleBudget>()
+            .init_resource::<OrganismIndex>()
+            .register_type::<PollinationFailed>()
+            .register_type::<Health>()
+            .register_type::<Lifespan>()
+            .register_type::<TreeVoxels>()
+            .register_type::<Moisture>()
+            .add_event::<SetVoxel>()
+            .add_event::<SpreadSeeds>()
+            .add_systems(
+                Update,
+                (
+                    assign_lifespan_system,
+                    seed_to_germinate_system,
+                    growth_system,
+                    maturation_system,
+                    pollination_system,
+                    fruiting_system,
+                    lifecycle_system,
+                    decay_system,
+                    resource_collection_system,
+                )
+                    .in_set(LifeCycleSet),
+            )
+            .add_systems(
+                PostUpdate,
+                (
+                    unindex_removed_organisms_system,
+                    index_moved_organisms_system,
+                )
+                    .chain(),
+            );
     }
 }
 
-pub fn get_random_surface_voxels(
-    voxel_world: &VoxelWorld,
-    position: IVec3,
-    radius: u8,
-    n: u8,
-) -> HashSet<(IVec3, WorldVoxel)> {
-    iter::repeat_with(|| voxel_world.get_random_surface_voxel(position, radius.into()))
-        .flatten()
-        .fold_while(HashSet::new(), |mut voxels, voxel| {
-            voxels.insert(voxel);
-            if voxels.len() < n as usize {
-                itertools::FoldWhile::Continue(voxels)
-            } else {
-                itertools::FoldWhile::Done(voxels)
-            }
-        })
-        .into_inner()
+#[cfg(test)]
+mod tests {
+    use crate::lifecycles::*;
+    use crate::soil::INITIAL_FERTILITY;
+    use crate::weather::Weather;
+    use bevy::ecs::event::Events;
+    use bevy::ecs::system::RunSystemOnce;
+    use std::time::Duration;
+
+    #[test]
+    fn apples_need_a_nearby_partner_to_pollinate() {
+        let registry = SpeciesRegistry::for_tests();
+        let apple = registry.by_name("Apple").unwrap();
+        let mut world = World::new();
+        world.insert_resource(registry);
+        world.init_resource::<Time>();
+        world.insert_resource(TimeOfDay::new(0.));
+        world.init_resource::<Weather>();
+        world.init_resource::<SoilFertility>();
+        world.init_resource::<Events<SetVoxel>>();
+        world.init_resource::<LifeCycleBudget>();
+        world.init_resource::<OrganismIndex>();
+        let mut spawn_apple = |pos: IVec3| {
+            world
+                .spawn((
+                    apple,
+                    Mature,
+                    HasPosition { pos },
+                    MatureAgeTimer(0.),
+                    Water(1),
+                    Health::default(),
+                    LastProcessed::default(),
+                ))
+                .id()
+        };
+        let lonely = spawn_apple(IVec3::new(-100, 0, 0));
+        let pair = [spawn_apple(IVec3::ZERO), spawn_apple(IVec3::new(3, 0, 4))];
+        let beside_fruiting = spawn_apple(IVec3::new(100, 0, 0));
+        world.spawn((
+            apple,
+            Fruiting,
+            HasPosition {
+                pos: IVec3::new(102, 0, 0),
+            },
+        ));
+
+        world.run_system_once(index_moved_organisms_system);
+        world.run_system_once(pollination_system);
+
+        assert!(world.get::<Pollinated>(beside_fruiting).is_some());
+        assert!(world.get::<Mature>(lonely).is_some());
+        assert_eq!(
+            world
+                .get::<PollinationFailed>(lonely)
+                .map(|failed| failed.attempts),
+            Some(1)
+        );
+        pair.into_iter().for_each(|organism| {
+            assert!(world.get::<Pollinated>(organism).is_some());
+            assert!(world.get::<Mature>(organism).is_none());
+            assert!(world.get::<PollinationFailed>(organism).is_none());
+        });
+    }
+
+    #[test]
+    fn harvested_wheat_leaves_tilled_soil() {
+        use crate::voxel_access::{VoxelAccess, VoxelGrid};
+        use crate::voxel_painting::apply_voxel_edits;
+        use bevy_voxel_world::prelude::*;
+
+        let registry = SpeciesRegistry::for_tests();
+        let wheat = registry.by_name("Wheat").unwrap();
+        let mut world = World::new();
+        world.insert_resource(registry);
+        world.init_resource::<SoilFertility>();
+        world.init_resource::<Events<SetVoxel>>();
+        world.init_resource::<Events<SpreadSeeds>>();
+        world.init_resource::<LifeCycleBudget>();
+        let player = world.spawn_empty().id();
+        let organism = world
+            .spawn((
+                wheat,
+                Fruiting,
+                HasPosition { pos: IVec3::Y },
+                Generations(2),
+                Lifespan(1),
+                OwnedBy { owner: player },
+            ))
+            .id();
+        let mut grid = VoxelGrid::default();
+        grid.set_voxel(IVec3::ZERO, WorldVoxel::Solid(BlockType::Dirt.index()));
+        grid.set_voxel(IVec3::Y, WorldVoxel::Solid(BlockType::Wheat.index()));
+
+        world.run_system_once(lifecycle_system);
+        let voxel_edits: Vec<SetVoxel> = world.resource_mut::<Events<SetVoxel>>().drain().collect();
+        apply_voxel_edits(&mut grid, voxel_edits);
+
+        assert!(world.get::<Dead>(organism).is_some());
+        assert_eq!(
+            grid.get_voxel(IVec3::ZERO),
+            WorldVoxel::Solid(BlockType::TilledSoil.index())
+        );
+        assert_eq!(grid.get_voxel(IVec3::Y), WorldVoxel::Air);
+    }
+
+    #[test]
+    fn thirsty_plants_starve() {
+        let registry = SpeciesRegistry::for_tests();
+        let apple = registry.by_name("Apple").unwrap();
+        let needs = registry.get(apple).growing_needs.clone();
+        let mut world = World::new();
+        world.insert_resource(registry);
+        world.insert_resource(TimeOfDay::new(0.));
+        world.init_resource::<Weather>();
+        world.init_resource::<SoilFertility>();
+        world.init_resource::<Events<SetVoxel>>();
+        world.init_resource::<LifeCycleBudget>();
+        let mut time = Time::<()>::default();
+        time.advance_by(Duration::from_secs(1));
+        world.insert_resource(time);
+        let organism = world
+            .spawn((
+                apple,
+                Growing { needs, drawn: 0. },
+                HasPosition { pos: IVec3::ZERO },
+                Water(0),
+                Soil(1),
+                Health(0.4),
+                LastProcessed::default(),
+            ))
+            .id();
+
+        world.run_system_once(maturation_system);
+
+        assert!(world.get::<Dead>(organism).is_some());
+        assert!(world.get::<Growing>(organism).is_none());
+        assert!(world.get::<DecayTimer>(organism).is_some());
+    }
+
+    #[test]
+    fn plants_draw_water_and_soil_for_the_time_they_waited() {
+        let draw = |organisms_per_frame: usize| {
+            let registry = SpeciesRegistry::for_tests();
+            let wheat = registry.by_name("Wheat").unwrap();
+            let needs = registry.get(wheat).growing_needs.clone();
+            let mut world = World::new();
+            world.insert_resource(registry);
+            world.insert_resource(TimeOfDay {
+                fraction: 0.5,
+                day_length: 0.,
+            });
+            world.init_resource::<Weather>();
+            world.init_resource::<SoilFertility>();
+            world.init_resource::<Events<SetVoxel>>();
+            world.insert_resource(LifeCycleBudget {
+                organisms_per_frame,
+            });
+            world.init_resource::<Time>();
+            let organisms: Vec<Entity> = (0..4)
+                .map(|x| {
+                    world
+                        .spawn((
+                            wheat,
+                            Growing {
+                                needs: needs.clone(),
+                                drawn: 0.,
+                            },
+                            HasPosition {
+                                pos: IVec3::new(x, 0, 0),
+                            },
+                            Water(1),
+                            Soil(INITIAL_FERTILITY),
+                            Health(MAX_HEALTH),
+                            LastProcessed(Some(0.)),
+                        ))
+                        .id()
+                })
+                .collect();
+            // In a schedule, so the system keeps its `Rotation` from frame to frame
+            let mut schedule = Schedule::default();
+            schedule.add_systems(maturation_system);
+            (0..16).for_each(|_| {
+                world
+                    .resource_mut::<Time>()
+                    .advance_by(Duration::from_millis(250));
+                schedule.run(&mut world);
+            });
+            organisms
+                .into_iter()
+                .map(|organism| {
+                    let needs = &world.get::<Growing>(organism).unwrap().needs;
+                    let waited = world.get::<LastProcessed>(organism).unwrap().0.unwrap();
+                    let fertility = world.resource::<SoilFertility>().get(IVec2::ZERO);
+                    (needs.water.0, needs.soil.0, waited as u32, fertility)
+                })
+                .collect::<Vec<_>>()
+        };
+
+        // One unit a second, in full light, whether the organisms had a turn every
+        // frame or one of them did
+        let every_frame = draw(4);
+        let in_turns = draw(1);
+        every_frame
+            .iter()
+            .chain(&in_turns)
+            .for_each(|(water, soil, seconds, _)| {
+                assert_eq!(*water, 10 - seconds);
+                assert_eq!(*soil, 10 - seconds);
+            });
+        assert_eq!(every_frame[0].2, 4);
+        assert_eq!(every_frame[0].3, INITIAL_FERTILITY - 4);
+    }
+
+    #[test]
+    fn mature_plants_starve_while_waiting_to_pollinate() {
+        let registry = SpeciesRegistry::for_tests();
+        let apple = registry.by_name("Apple").unwrap();
+        let mut world = World::new();
+        world.insert_resource(registry);
+        world.insert_resource(TimeOfDay::new(0.));
+        world.init_resource::<Weather>();
+        world.init_resource::<SoilFertility>();
+        world.init_resource::<OrganismIndex>();
+        world.init_resource::<Events<SetVoxel>>();
+        world.init_resource::<LifeCycleBudget>();
+        let mut time = Time::<()>::default();
+        time.advance_by(Duration::from_secs(1));
+        world.insert_resource(time);
+        let organism = world
+            .spawn((
+                apple,
+                Mature,
+                HasPosition { pos: IVec3::ZERO },
+                MatureAgeTimer(100.),
+                Water(0),
+                Health(0.4),
+                LastProcessed::default(),
+            ))
+            .id();
+
+        world.run_system_once(pollination_system);
+
+        assert!(world.get::<Dead>(organism).is_some());
+        assert!(world.get::<Mature>(organism).is_none());
+    }
+
+    #[test]
+    fn fruit_is_picked_on_the_next_turn() {
+        let registry = SpeciesRegistry::for_tests();
+        let wheat = registry.by_name("Wheat").unwrap();
+        let mut world = World::new();
+        world.insert_resource(registry);
+        world.init_resource::<SoilFertility>();
+        world.init_resource::<Events<SetVoxel>>();
+        world.init_resource::<Events<SpreadSeeds>>();
+        world.init_resource::<LifeCycleBudget>();
+        let organism = world
+            .spawn((
+                wheat,
+                Fruiting,
+                HasPosition { pos: IVec3::Y },
+                Generations(1),
+                Lifespan(5),
+            ))
+            .id();
+
+        world.run_system_once(lifecycle_system);
+
+        assert!(world.get::<Fruiting>(organism).is_none());
+        assert!(world.get::<Mature>(organism).is_some());
+    }
+
+    #[test]
+    fn every_organism_gets_a_turn() {
+        let budget = LifeCycleBudget {
+            organisms_per_frame: 2,
+        };
+        let mut world = World::new();
+        let organisms: Vec<Entity> = (0..5).map(|_| world.spawn_empty().id()).collect();
+        let mut rotation = Rotation::default();
+        let turns: Vec<Vec<Entity>> = (0..3)
+            .map(|_| rotation.next_turn(&budget, || organisms.clone()))
+            .collect();
+
+        assert!(turns
+            .iter()
+            .all(|turn| turn.len() <= budget.organisms_per_frame));
+        organisms.iter().for_each(|organism| {
+            assert_eq!(
+                turns.iter().filter(|turn| turn.contains(organism)).count(),
+                1
+            );
+        });
+        // The next pass picks up where this one stopped, with the organisms there are by then
+        let newcomer = world.spawn_empty().id();
+        let everyone: Vec<Entity> = organisms.iter().copied().chain([newcomer]).collect();
+        assert_eq!(
+            rotation.next_turn(&budget, || everyone.clone()),
+            vec![organisms[0], organisms[1]]
+        );
+    }
 }
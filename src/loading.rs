@@ -1,3 +1,4 @@
+use crate::species::{build_species_registry, SpeciesDefLoader, SpeciesFile};
 use crate::GameState;
 use bevy::prelude::*;
 use bevy_asset_loader::prelude::*;
@@ -10,11 +11,15 @@ pub struct LoadingPlugin;
 /// If interested, take a look at <https://bevy-cheatbook.github.io/features/assets.html>
 impl Plugin for LoadingPlugin {
     fn build(&self, app: &mut App) {
-        app.add_loading_state(
-            LoadingState::new(GameState::Loading).continue_to_state(GameState::Menu),
-        )
-        .add_collection_to_loading_state::<_, AudioAssets>(GameState::Loading)
-        .add_collection_to_loading_state::<_, TextureAssets>(GameState::Loading);
+        app.init_asset::<SpeciesFile>()
+            .register_asset_loader(SpeciesDefLoader)
+            .add_loading_state(
+                LoadingState::new(GameState::Loading).continue_to_state(GameState::Menu),
+            )
+            .add_collection_to_loading_state::<_, AudioAssets>(GameState::Loading)
+            .add_collection_to_loading_state::<_, TextureAssets>(GameState::Loading)
+            .add_collection_to_loading_state::<_, SpeciesAssets>(GameState::Loading)
+            .add_systems(OnExit(GameState::Loading), build_species_registry);
     }
 }
 
@@ -36,3 +41,11 @@ pub struct TextureAssets {
     #[asset(path = "textures/crosshair.png")]
     pub crosshair: Handle<Image>,
 }
+
+/// Every `*.species.ron` file in `assets/species`; see `SpeciesRegistry`. Invalid
+/// files load too, see `SpeciesFile`, so they don't keep the game in `GameState::Loading`.
+#[derive(AssetCollection, Resource)]
+pub struct SpeciesAssets {
+    #[asset(path = "species", collection(typed))]
+    pub species: Vec<Handle<SpeciesFile>>,
+}
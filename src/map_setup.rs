@@ -1,54 +1,237 @@
+use crate::biome::Biome;
 use crate::block_types::*;
-use bevy::{pbr::CascadeShadowConfigBuilder, prelude::*, utils::HashMap};
+use crate::day_night::Sun;
+use crate::heightmap::{Heightmap, HeightmapCache};
+use crate::region_store::RegionStore;
+use crate::sim_rng::WorldSeed;
+use crate::waterways::{NearbyWaters, Waterways};
+use crate::world_gen::WorldGenSettings;
+use bevy::{pbr::CascadeShadowConfigBuilder, prelude::*};
 use bevy_voxel_world::prelude::*;
-use noise::{HybridMulti, NoiseFn, Perlin};
+use noise::{HybridMulti, MultiFractal, NoiseFn, Perlin};
 use std::sync::Arc;
 
-fn get_voxel_fn() -> Box<dyn FnMut(IVec3) -> WorldVoxel + Send + Sync> {
-    // Set up some noise to use as the terrain height map
-    let mut noise = HybridMulti::<Perlin>::new(1234);
-    noise.octaves = 5;
-    noise.frequency = 1.1;
-    noise.lacunarity = 2.8;
-    noise.persistence = 0.4;
+/// Ground whose surface is less than this far above the sea is beach
+const BEACH_HEIGHT: i32 = 2;
+/// Climate changes over a much larger scale than the height of the terrain
+const CLIMATE_SCALE: f64 = 400.0;
+/// How much colder the climate gets with each voxel of height
+const TEMPERATURE_LAPSE: f64 = 1.0 / 50.0;
+/// Voxels of topsoil under the surface block, then of subsoil under that, with stone below
+const TOPSOIL_DEPTH: i32 = 3;
+const SUBSOIL_DEPTH: i32 = 4;
+/// Everything at or below this is bedrock
+const BEDROCK_LEVEL: i32 = -64;
+/// Size of the winding tunnels the caves are made of
+const CAVE_SCALE: f64 = 40.0;
+/// Cave noise must be above this, so caves take up only some of the stone
+const CAVE_THRESHOLD: f64 = 0.55;
 
-    // We use this to cache the noise value for each y column so we only need
-    // to calculate it once per x/z coordinate
-    let mut cache = HashMap::<(i32, i32), f64>::new();
+/// The terrain as it is generated, before any voxel edits. Wild vegetation is placed
+/// with this, since a chunk's voxels aren't available yet when it starts spawning.
+#[derive(Resource, Clone)]
+pub struct TerrainGenerator {
+    noise: HybridMulti<Perlin>,
+    height_scale: f64,
+    sea_level: i32,
+    temperature: Perlin,
+    moisture: Perlin,
+    waterways: Waterways,
+    caves: Option<Perlin>,
+}
 
-    // Then we return this boxed closure that captures the noise and the cache
-    // This will get sent off to a separate thread for meshing by bevy_voxel_world
-    Box::new(move |pos: IVec3| {
-        // Sea level
-        if pos.y < 1 {
-            return WorldVoxel::Solid(3);
+/// What the terrain generator works out once for each column
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct TerrainColumn {
+    /// Voxels in the column below this height are ground
+    pub height: f64,
+    /// Rivers and lakes fill the column with water up to this level
+    pub water_level: Option<i32>,
+    pub biome: Biome,
+}
+
+impl TerrainGenerator {
+    pub fn new(settings: &WorldGenSettings) -> Self {
+        let noise_seed = WorldSeed(settings.seed).noise_seed();
+        // Set up some noise to use as the terrain height map
+        // Through the setters, as only they build a source for every octave
+        let noise = HybridMulti::<Perlin>::new(noise_seed)
+            .set_octaves(settings.octaves)
+            .set_frequency(settings.frequency)
+            .set_lacunarity(settings.lacunarity)
+            .set_persistence(settings.persistence);
+        TerrainGenerator {
+            noise,
+            height_scale: settings.height_scale,
+            sea_level: settings.sea_level,
+            temperature: Perlin::new(noise_seed.wrapping_add(1)),
+            moisture: Perlin::new(noise_seed.wrapping_add(2)),
+            waterways: Waterways::new(noise_seed),
+            caves: settings
+                .caves
+                .then(|| Perlin::new(noise_seed.wrapping_add(4))),
         }
+    }
 
-        let [x, y, z] = pos.as_dvec3().to_array();
+    /// Voxels in the column below this height are ground, before rivers and lakes are carved out
+    pub fn height(&self, x: i32, z: i32) -> f64 {
+        self.noise.get([x as f64 / 1000.0, z as f64 / 1000.0]) * self.height_scale
+    }
 
-        // If y is less than the noise sample, we will set the voxel to solid
-        let is_ground = y < match cache.get(&(pos.x, pos.z)) {
-            Some(sample) => *sample,
-            None => {
-                let sample = noise.get([x / 1000.0, z / 1000.0]) * 50.0;
-                cache.insert((pos.x, pos.z), sample);
-                sample
-            }
+    /// The biome of the column at `x`, `z`. High ground is colder than the
+    /// climate around it, so hilltops are alpine.
+    pub fn biome(&self, x: i32, z: i32) -> Biome {
+        self.column(x, z).biome
+    }
+
+    pub fn column(&self, x: i32, z: i32) -> TerrainColumn {
+        let column = IVec2::new(x, z);
+        self.column_among(&self.waters_around(column, column), x, z)
+    }
+
+    /// The rivers and lakes that could reach any column from `min` to `max`
+    pub fn waters_around(&self, min: IVec2, max: IVec2) -> NearbyWaters {
+        self.waterways
+            .around(min, max, |x, z| self.height(x, z), self.sea_level)
+    }
+
+    /// `column`, with the rivers and lakes around it already looked up
+    pub fn column_among(&self, waters: &NearbyWaters, x: i32, z: i32) -> TerrainColumn {
+        let (height, water_level) = waters.carve(x, z, self.height(x, z));
+        let climate = |noise: &Perlin| {
+            0.5 + 0.5 * noise.get([x as f64 / CLIMATE_SCALE, z as f64 / CLIMATE_SCALE])
         };
+        let temperature = climate(&self.temperature) - height.max(0.0) * TEMPERATURE_LAPSE;
+        TerrainColumn {
+            height,
+            water_level,
+            biome: Biome::from_climate(temperature, climate(&self.moisture)),
+        }
+    }
+
+    /// Caves are hollowed out below the subsoil, so they never break through to the surface
+    fn is_cave(&self, pos: IVec3) -> bool {
+        self.caves.as_ref().is_some_and(|caves| {
+            pos.y > BEDROCK_LEVEL
+                && caves.get((pos.as_dvec3() / CAVE_SCALE).to_array()) > CAVE_THRESHOLD
+        })
+    }
 
-        if is_ground {
-            // Solid voxel of material type 0
-            WorldVoxel::Solid(BlockType::Dirt.index())
+    /// Layers of topsoil, subsoil and stone under the biome's surface block, or
+    /// under sand along the shore and under the sea, rivers and lakes
+    pub fn voxel(&self, pos: IVec3, column: &TerrainColumn) -> WorldVoxel {
+        let surface_y = column.height.ceil() as i32 - 1;
+        let water_level = self.water_level(column);
+        let block = if pos.y > surface_y {
+            if pos.y < water_level {
+                BlockType::Water
+            } else {
+                return WorldVoxel::Air;
+            }
+        } else if pos.y <= BEDROCK_LEVEL {
+            BlockType::Bedrock
+        } else if pos.y < surface_y - TOPSOIL_DEPTH - SUBSOIL_DEPTH && self.is_cave(pos) {
+            return WorldVoxel::Air;
+        } else if pos.y < surface_y - TOPSOIL_DEPTH - SUBSOIL_DEPTH {
+            BlockType::Stone
+        } else if pos.y < surface_y - TOPSOIL_DEPTH {
+            BlockType::Subsoil
+        } else if pos.y < surface_y {
+            BlockType::Dirt
+        } else if surface_y < self.sea_level + BEACH_HEIGHT || surface_y < water_level {
+            BlockType::Sand
         } else {
-            WorldVoxel::Air
+            column.biome.surface_block()
+        };
+        WorldVoxel::Solid(block.index())
+    }
+
+    /// Drops the rivers and lakes that can't reach within `distance` columns of `center`
+    pub fn evict_waters_beyond(&self, center: IVec2, distance: i32) {
+        self.waterways.evict_beyond(center, distance);
+    }
+
+    /// The column is filled with water up to this level, by the sea or a river or lake
+    fn water_level(&self, column: &TerrainColumn) -> i32 {
+        column
+            .water_level
+            .map_or(self.sea_level, |level| level.max(self.sea_level))
+    }
+
+    /// Whether the column's ground is under water
+    pub fn is_under_water(&self, column: &TerrainColumn) -> bool {
+        (column.height.ceil() as i32) < self.water_level(column)
+    }
+
+    /// `voxel`, or for `WorldVoxel::Unset` the voxel generated at `pos`
+    pub fn or_generated(&self, pos: IVec3, voxel: WorldVoxel) -> WorldVoxel {
+        match voxel {
+            WorldVoxel::Unset => self.voxel(pos, &self.column(pos.x, pos.z)),
+            voxel => voxel,
         }
+    }
+
+    /// The topmost solid voxel in the column at `x`, `z`
+    pub fn surface_voxel(&self, x: i32, z: i32) -> (IVec3, WorldVoxel) {
+        let column = self.column(x, z);
+        let pos = IVec3::new(
+            x,
+            (column.height.ceil() as i32 - 1)
+                .max(self.sea_level - 1)
+                .max(column.water_level.unwrap_or(i32::MIN) - 1),
+            z,
+        );
+        (pos, self.voxel(pos, &column))
+    }
+}
+
+pub fn get_voxel_fn(
+    terrain: TerrainGenerator,
+    cache: HeightmapCache,
+    chunk_pos: IVec3,
+) -> Box<dyn FnMut(IVec3) -> WorldVoxel + Send + Sync> {
+    // The noise values for each y column are shared by all the chunks stacked
+    // in it, through the cache. They're looked up on the first call rather than
+    // here, so that working them out happens off the main thread.
+    let mut heightmap: Option<Arc<Heightmap>> = None;
+
+    // Then we return this boxed closure that captures the terrain and the cache
+    // This will get sent off to a separate thread for meshing by bevy_voxel_world
+    Box::new(move |pos: IVec3| {
+        let heightmap =
+            heightmap.get_or_insert_with(|| cache.get_or_insert(&terrain, chunk_pos.xz()));
+        let column = heightmap
+            .get(pos.x, pos.z)
+            .copied()
+            .unwrap_or_else(|| terrain.column(pos.x, pos.z));
+        terrain.voxel(pos, &column)
     })
 }
 
-pub fn map_setup(mut commands: Commands) {
+/// Holds off generating any chunks until the world is set up in `map_setup`, as its
+/// `WorldGenSettings` may still change in the menu
+pub fn defer_chunk_spawning(mut commands: Commands) {
+    commands.insert_resource(VoxelWorldConfiguration {
+        spawning_distance: 0,
+        ..Default::default()
+    });
+}
+
+/// Generates the world from the `WorldGenSettings`, with the voxel edits in the `RegionStore`
+pub fn map_setup(
+    mut commands: Commands,
+    settings: Res<WorldGenSettings>,
+    region_store: Res<RegionStore>,
+) {
+    info!("generating world: {settings:?}");
+    let terrain = TerrainGenerator::new(&settings);
+    commands.insert_resource(terrain.clone());
+    let heightmap_cache = HeightmapCache::default();
+    commands.insert_resource(heightmap_cache.clone());
+    let region_store = region_store.clone();
     commands.insert_resource(VoxelWorldConfiguration {
         // This is the spawn distance (in 32 meter chunks), centered around the camera.
-        spawning_distance: 25,
+        spawning_distance: settings.spawning_distance,
 
         // Here we supply a closure that returns another closure
         // that returns a voxel value for a given position.
@@ -56,7 +239,20 @@ pub fn map_setup(mut commands: Commands) {
         // data in a sendable closure to be sent off
         // to a differrent thread for the meshing process.
         // A new closure is fetched for each chunk.
-        voxel_lookup_delegate: Box::new(move |_chunk_pos| get_voxel_fn()),
+        // Edits are read from the chunk's region file as it generates, rather than
+        // given to the voxel world, which would keep them all in memory
+        voxel_lookup_delegate: Box::new(move |chunk_pos| {
+            let mut generated = get_voxel_fn(terrain.clone(), heightmap_cache.clone(), chunk_pos);
+            let region_store = region_store.clone();
+            let mut edits = None;
+            Box::new(move |pos: IVec3| {
+                edits
+                    .get_or_insert_with(|| region_store.chunk_edits(chunk_pos))
+                    .get(&pos)
+                    .copied()
+                    .unwrap_or_else(|| generated(pos))
+            })
+        }),
         // `get_voxel_fn` is defined below
         texture_index_mapper: Arc::new(|vox_mat: u8| {
             BLOCK_TO_TILES_MAP
@@ -69,23 +265,131 @@ pub fn map_setup(mut commands: Commands) {
 
     // --- Just scene setup below ---
 
-    // Sun
+    // Sun, moved through the sky by the DayNightPlugin
     let cascade_shadow_config = CascadeShadowConfigBuilder { ..default() }.build();
-    commands.spawn(DirectionalLightBundle {
-        directional_light: DirectionalLight {
-            color: Color::rgb(0.98, 0.95, 0.82),
-            shadows_enabled: true,
+    commands.spawn((
+        DirectionalLightBundle {
+            directional_light: DirectionalLight {
+                color: Color::rgb(0.98, 0.95, 0.82),
+                shadows_enabled: true,
+                ..default()
+            },
+            transform: Transform::from_xyz(0.0, 0.0, 0.0)
+                .looking_at(Vec3::new(-0.15, -0.1, 0.15), Vec3::Y),
+            cascade_shadow_config,
             ..default()
         },
-        transform: Transform::from_xyz(0.0, 0.0, 0.0)
-            .looking_at(Vec3::new(-0.15, -0.1, 0.15), Vec3::Y),
-        cascade_shadow_config,
-        ..default()
-    });
+        Sun,
+    ));
 
-    // Ambient light, same color as sun
+    // Ambient light, same color as sun; brightness follows the time of day
     commands.insert_resource(AmbientLight {
         color: Color::rgb(0.98, 0.95, 0.82),
         brightness: 0.3,
     });
 }
+
+#[cfg(test)]
+mod tests {
+    use crate::map_setup::*;
+
+    #[test]
+    fn terrain_has_sea_beaches_and_grass() {
+        let terrain = TerrainGenerator::new(&WorldGenSettings {
+            seed: 0,
+            caves: false,
+            ..default()
+        });
+        let block = |y: i32, height: f64| {
+            let column = TerrainColumn {
+                height,
+                water_level: None,
+                biome: Biome::Meadow,
+            };
+            terrain.voxel(IVec3::new(0, y, 0), &column)
+        };
+        let solid = |block_type: BlockType| WorldVoxel::Solid(block_type.index());
+
+        // Under the sea
+        assert_eq!(block(0, -3.5), solid(BlockType::Water));
+        assert_eq!(block(-4, -3.5), solid(BlockType::Sand));
+        assert_eq!(block(-5, -3.5), solid(BlockType::Dirt));
+        // On the shore
+        assert_eq!(block(1, 1.5), solid(BlockType::Sand));
+        assert_eq!(block(2, 1.5), WorldVoxel::Air);
+        // Inland
+        assert_eq!(block(10, 10.2), solid(BlockType::Grass));
+        assert_eq!(block(11, 10.2), WorldVoxel::Air);
+        // Underground
+        assert_eq!(block(7, 10.2), solid(BlockType::Dirt));
+        assert_eq!(block(6, 10.2), solid(BlockType::Subsoil));
+        assert_eq!(block(2, 10.2), solid(BlockType::Stone));
+        assert_eq!(block(BEDROCK_LEVEL, 10.2), solid(BlockType::Bedrock));
+        // In a lake
+        let lake = TerrainColumn {
+            height: 7.5,
+            water_level: Some(10),
+            biome: Biome::Meadow,
+        };
+        let lake_block = |y: i32| terrain.voxel(IVec3::new(0, y, 0), &lake);
+        assert_eq!(lake_block(9), solid(BlockType::Water));
+        assert_eq!(lake_block(8), solid(BlockType::Water));
+        assert_eq!(lake_block(7), solid(BlockType::Sand));
+        assert_eq!(lake_block(10), WorldVoxel::Air);
+        assert!(terrain.is_under_water(&lake));
+        assert!(!terrain.is_under_water(&TerrainColumn {
+            water_level: Some(8),
+            ..lake
+        }));
+    }
+
+    #[test]
+    fn biomes_have_their_own_surface() {
+        let terrain = TerrainGenerator::new(&WorldGenSettings {
+            seed: 0,
+            ..default()
+        });
+        let alpine = TerrainColumn {
+            height: 10.2,
+            water_level: None,
+            biome: Biome::Alpine,
+        };
+        let solid = |block_type: BlockType| WorldVoxel::Solid(block_type.index());
+        assert_eq!(
+            terrain.voxel(IVec3::new(0, 10, 0), &alpine),
+            solid(BlockType::Snow)
+        );
+        assert_eq!(
+            terrain.voxel(
+                IVec3::new(0, 1, 0),
+                &TerrainColumn {
+                    height: 1.5,
+                    ..alpine
+                }
+            ),
+            solid(BlockType::Sand)
+        );
+
+        // Caves open up in the stone, but leave the topsoil and subsoil alone
+        let column = TerrainColumn {
+            height: 0.5,
+            water_level: None,
+            biome: Biome::Meadow,
+        };
+        let caves: Vec<IVec3> = (-60..=0)
+            .flat_map(|y| (0..200).map(move |x| IVec3::new(x, y, 0)))
+            .filter(|pos| terrain.voxel(*pos, &column) == WorldVoxel::Air)
+            .collect();
+        assert!(!caves.is_empty());
+        let stone_top = -TOPSOIL_DEPTH - SUBSOIL_DEPTH - 1;
+        assert!(caves.iter().all(|cave| cave.y <= stone_top));
+        assert_eq!(
+            terrain.voxel(IVec3::new(0, stone_top + 1, 0), &column),
+            WorldVoxel::Solid(BlockType::Subsoil.index())
+        );
+
+        // The climate varies across the map
+        let biomes: Vec<Biome> = (0..200).map(|ix| terrain.biome(ix * 97, ix * 61)).collect();
+        assert!(biomes.iter().any(|biome| *biome != biomes[0]));
+    }
+}
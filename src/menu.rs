@@ -1,8 +1,12 @@
 use crate::core_components::ChangeState;
 use crate::loading::TextureAssets;
+use crate::map_setup::TerrainGenerator;
+use crate::save_load::{latest_save_slot, saved_world_gen, CurrentSaveSlot, PendingLoad};
 use crate::timer::GameTimer;
+use crate::world_gen::{WorldGenSetting, WorldGenSettings};
 use crate::GameState;
 use bevy::prelude::*;
+use strum::IntoEnumIterator;
 
 pub struct MenuPlugin;
 
@@ -11,7 +15,16 @@ pub struct MenuPlugin;
 impl Plugin for MenuPlugin {
     fn build(&self, app: &mut App) {
         app.add_systems(OnEnter(GameState::Menu), setup_menu)
-            .add_systems(Update, click_play_button.run_if(in_state(GameState::Menu)))
+            .add_systems(
+                Update,
+                (
+                    click_play_button,
+                    click_new_world_buttons,
+                    update_setting_values.run_if(resource_changed::<WorldGenSettings>()),
+                )
+                    .chain()
+                    .run_if(in_state(GameState::Menu)),
+            )
             .add_systems(OnExit(GameState::Menu), cleanup_menu);
     }
 }
@@ -34,8 +47,17 @@ impl Default for ButtonColors {
 #[derive(Component)]
 struct Menu;
 
-fn setup_menu(mut commands: Commands, textures: Res<TextureAssets>) {
+fn setup_menu(
+    mut commands: Commands,
+    textures: Res<TextureAssets>,
+    terrain: Option<Res<TerrainGenerator>>,
+) {
     info!("menu");
+    spawn_main_menu(&mut commands, &textures, terrain.is_some());
+}
+
+/// The world can only be set up before it has been generated
+fn spawn_main_menu(commands: &mut Commands, textures: &TextureAssets, world_generated: bool) {
     commands
         .spawn((
             NodeBundle {
@@ -52,6 +74,37 @@ fn setup_menu(mut commands: Commands, textures: Res<TextureAssets>) {
             Menu,
         ))
         .with_children(|children| {
+            if let Some(slot) = latest_save_slot() {
+                let button_colors = ButtonColors::default();
+                children
+                    .spawn((
+                        ButtonBundle {
+                            style: Style {
+                                width: Val::Px(200.0),
+                                height: Val::Px(50.0),
+                                margin: UiRect::bottom(Val::Px(10.)),
+                                justify_content: JustifyContent::Center,
+                                align_items: AlignItems::Center,
+                                ..Default::default()
+                            },
+                            background_color: button_colors.normal.into(),
+                            ..Default::default()
+                        },
+                        button_colors,
+                        ChangeState(GameState::Playing),
+                        ContinueGame(slot),
+                    ))
+                    .with_children(|parent| {
+                        parent.spawn(TextBundle::from_section(
+                            "Continue",
+                            TextStyle {
+                                font_size: 40.0,
+                                color: Color::rgb(0.9, 0.9, 0.9),
+                                ..default()
+                            },
+                        ));
+                    });
+            }
             let button_colors = ButtonColors::default();
             children
                 .spawn((
@@ -79,6 +132,14 @@ fn setup_menu(mut commands: Commands, textures: Res<TextureAssets>) {
                         },
                     ));
                 });
+            if !world_generated {
+                spawn_button(
+                    children,
+                    200.0,
+                    "New World",
+                    OpenScreen(MenuScreen::NewWorld),
+                );
+            }
         });
     commands
         .spawn((
@@ -176,9 +237,140 @@ fn setup_menu(mut commands: Commands, textures: Res<TextureAssets>) {
         });
 }
 
+/// Lets the world's `WorldGenSettings` be changed before it is generated
+fn spawn_new_world_menu(commands: &mut Commands, settings: &WorldGenSettings) {
+    let text_style = |font_size: f32| TextStyle {
+        font_size,
+        color: Color::rgb(0.9, 0.9, 0.9),
+        ..default()
+    };
+    commands
+        .spawn((
+            NodeBundle {
+                style: Style {
+                    width: Val::Percent(100.0),
+                    height: Val::Percent(100.0),
+                    flex_direction: FlexDirection::Column,
+                    align_items: AlignItems::Center,
+                    justify_content: JustifyContent::Center,
+                    ..default()
+                },
+                ..default()
+            },
+            Menu,
+        ))
+        .with_children(|children| {
+            children.spawn(TextBundle::from_section("New World", text_style(40.0)));
+            WorldGenSetting::iter().for_each(|setting| {
+                children
+                    .spawn(NodeBundle {
+                        style: Style {
+                            flex_direction: FlexDirection::Row,
+                            align_items: AlignItems::Center,
+                            ..default()
+                        },
+                        ..default()
+                    })
+                    .with_children(|row| {
+                        row.spawn(
+                            TextBundle::from_section(setting.label(), text_style(25.0)).with_style(
+                                Style {
+                                    width: Val::Px(180.0),
+                                    ..default()
+                                },
+                            ),
+                        );
+                        spawn_button(row, 40.0, "-", AdjustSetting(setting, -1));
+                        row.spawn((
+                            TextBundle::from_section(
+                                setting.value_text(settings),
+                                text_style(25.0),
+                            )
+                            .with_style(Style {
+                                width: Val::Px(260.0),
+                                justify_content: JustifyContent::Center,
+                                ..default()
+                            }),
+                            SettingValue(setting),
+                        ));
+                        spawn_button(row, 40.0, "+", AdjustSetting(setting, 1));
+                    });
+            });
+            spawn_button(children, 200.0, "Random seed", RandomSeed);
+            children
+                .spawn(NodeBundle {
+                    style: Style {
+                        flex_direction: FlexDirection::Row,
+                        ..default()
+                    },
+                    ..default()
+                })
+                .with_children(|row| {
+                    spawn_button(row, 140.0, "Back", OpenScreen(MenuScreen::Main));
+                    spawn_button(row, 140.0, "Create", ChangeState(GameState::Playing));
+                });
+        });
+}
+
+fn spawn_button(parent: &mut ChildBuilder, width: f32, label: &str, action: impl Bundle) {
+    let button_colors = ButtonColors::default();
+    parent
+        .spawn((
+            ButtonBundle {
+                style: Style {
+                    width: Val::Px(width),
+                    height: Val::Px(40.0),
+                    margin: UiRect::all(Val::Px(5.)),
+                    justify_content: JustifyContent::Center,
+                    align_items: AlignItems::Center,
+                    ..Default::default()
+                },
+                background_color: button_colors.normal.into(),
+                ..Default::default()
+            },
+            button_colors,
+            action,
+        ))
+        .with_children(|parent| {
+            parent.spawn(TextBundle::from_section(
+                label,
+                TextStyle {
+                    font_size: 30.0,
+                    color: Color::rgb(0.9, 0.9, 0.9),
+                    ..default()
+                },
+            ));
+        });
+}
+
 #[derive(Component)]
 struct OpenLink(&'static str);
 
+#[derive(Clone, Copy, Debug)]
+enum MenuScreen {
+    Main,
+    NewWorld,
+}
+
+/// Replaces the menu with another screen
+#[derive(Component)]
+struct OpenScreen(MenuScreen);
+
+/// Steps a world generation setting up or down
+#[derive(Component)]
+struct AdjustSetting(WorldGenSetting, i32);
+
+#[derive(Component)]
+struct RandomSeed;
+
+/// Shows the current value of a world generation setting
+#[derive(Component)]
+struct SettingValue(WorldGenSetting);
+
+/// Loads the given save slot when entering `GameState::Playing`
+#[derive(Component)]
+struct ContinueGame(u8);
+
 fn click_play_button(
     mut next_state: ResMut<NextState<GameState>>,
     mut interaction_query: Query<
@@ -188,15 +380,29 @@ fn click_play_button(
             &ButtonColors,
             Option<&ChangeState>,
             Option<&OpenLink>,
+            Option<&ContinueGame>,
         ),
         (Changed<Interaction>, With<Button>),
     >,
     timer_query: Query<&GameTimer>,
+    terrain: Option<Res<TerrainGenerator>>,
     mut commands: Commands,
 ) {
-    for (interaction, mut color, button_colors, change_state, open_link) in &mut interaction_query {
+    for (interaction, mut color, button_colors, change_state, open_link, continue_game) in
+        &mut interaction_query
+    {
         match *interaction {
             Interaction::Pressed => {
+                if let Some(ContinueGame(slot)) = continue_game {
+                    commands.insert_resource(CurrentSaveSlot(*slot));
+                    commands.insert_resource(PendingLoad(*slot));
+                    // Generate the same world the slot was saved in
+                    if terrain.is_none() {
+                        if let Some(world_gen) = saved_world_gen(*slot) {
+                            commands.insert_resource(world_gen);
+                        }
+                    }
+                }
                 if let Some(state) = change_state {
                     if timer_query.is_empty() {
                         commands.spawn(GameTimer {
@@ -222,6 +428,49 @@ fn click_play_button(
     }
 }
 
+fn click_new_world_buttons(
+    mut commands: Commands,
+    textures: Res<TextureAssets>,
+    terrain: Option<Res<TerrainGenerator>>,
+    mut settings: ResMut<WorldGenSettings>,
+    interaction_query: Query<
+        (
+            &Interaction,
+            Option<&OpenScreen>,
+            Option<&AdjustSetting>,
+            Option<&RandomSeed>,
+        ),
+        (Changed<Interaction>, With<Button>),
+    >,
+    menu: Query<Entity, With<Menu>>,
+) {
+    for (interaction, open_screen, adjust_setting, random_seed) in &interaction_query {
+        if *interaction != Interaction::Pressed {
+            continue;
+        }
+        if let Some(AdjustSetting(setting, steps)) = adjust_setting {
+            setting.step(&mut settings, *steps);
+        } else if random_seed.is_some() {
+            settings.seed = rand::random();
+        } else if let Some(OpenScreen(screen)) = open_screen {
+            menu.for_each(|entity| commands.entity(entity).despawn_recursive());
+            match screen {
+                MenuScreen::Main => spawn_main_menu(&mut commands, &textures, terrain.is_some()),
+                MenuScreen::NewWorld => spawn_new_world_menu(&mut commands, &settings),
+            }
+        }
+    }
+}
+
+fn update_setting_values(
+    settings: Res<WorldGenSettings>,
+    mut values: Query<(&mut Text, &SettingValue)>,
+) {
+    values.for_each_mut(|(mut text, SettingValue(setting))| {
+        text.sections[0].value = setting.value_text(&settings);
+    });
+}
+
 fn cleanup_menu(mut commands: Commands, menu: Query<Entity, With<Menu>>) {
     for entity in menu.iter() {
         commands.entity(entity).despawn_recursive();
@@ -0,0 +1,167 @@
+use crate::core_components::HasPosition;
+use bevy::prelude::*;
+use bevy::utils::HashMap;
+
+/// Same size as the voxel world's chunks
+pub const CHUNK_SIZE: i32 = 32;
+
+/// Finds organisms by position without scanning every `HasPosition`. At most one
+/// organism is indexed per voxel. Kept up to date in `PostUpdate`, but code that
+/// spawns organisms should also `insert` them straight away, so that a second
+/// organism isn't spawned on the same voxel before then.
+#[derive(Resource, Default, Debug)]
+pub struct OrganismIndex {
+    chunks: HashMap<IVec3, HashMap<IVec3, Entity>>,
+    positions: HashMap<Entity, IVec3>,
+}
+
+impl OrganismIndex {
+    pub fn chunk_of(pos: IVec3) -> IVec3 {
+        pos.div_euclid(IVec3::splat(CHUNK_SIZE))
+    }
+
+    pub fn get(&self, pos: IVec3) -> Option<Entity> {
+        self.chunks
+            .get(&OrganismIndex::chunk_of(pos))
+            .and_then(|organisms| organisms.get(&pos))
+            .copied()
+    }
+
+    pub fn position(&self, entity: Entity) -> Option<IVec3> {
+        self.positions.get(&entity).copied()
+    }
+
+    pub fn is_occupied(&self, pos: IVec3) -> bool {
+        self.get(pos).is_some()
+    }
+
+    /// Moves `entity` to `pos`, replacing and returning any other organism indexed there
+    pub fn insert(&mut self, entity: Entity, pos: IVec3) -> Option<Entity> {
+        self.remove(entity);
+        let replaced = self
+            .chunks
+            .entry(OrganismIndex::chunk_of(pos))
+            .or_default()
+            .insert(pos, entity);
+        if let Some(replaced) = replaced {
+            self.positions.remove(&replaced);
+        }
+        self.positions.insert(entity, pos);
+        replaced
+    }
+
+    /// Returns where `entity` was indexed, if it was
+    pub fn remove(&mut self, entity: Entity) -> Option<IVec3> {
+        let pos = self.positions.remove(&entity)?;
+        let chunk = OrganismIndex::chunk_of(pos);
+        if let Some(organisms) = self.chunks.get_mut(&chunk) {
+            organisms.remove(&pos);
+            if organisms.is_empty() {
+                self.chunks.remove(&chunk);
+            }
+        }
+        Some(pos)
+    }
+
+    #[cfg(test)]
+    pub fn len(&self) -> usize {
+        self.positions.len()
+    }
+
+    #[cfg(test)]
+    pub fn is_empty(&self) -> bool {
+        self.positions.is_empty()
+    }
+
+    /// Organisms with `min <= pos <= max` on every axis
+    pub fn in_box(&self, min: IVec3, max: IVec3) -> impl Iterator<Item = (IVec3, Entity)> + '_ {
+        let (min_chunk, max_chunk) = (OrganismIndex::chunk_of(min), OrganismIndex::chunk_of(max));
+        (min_chunk.x..=max_chunk.x)
+            .flat_map(move |x| {
+                (min_chunk.y..=max_chunk.y).flat_map(move |y| {
+                    (min_chunk.z..=max_chunk.z).map(move |z| IVec3::new(x, y, z))
+                })
+            })
+            .filter_map(|chunk| self.chunks.get(&chunk))
+            .flat_map(|organisms| organisms.iter())
+            .filter(move |(pos, _)| pos.cmpge(min).all() && pos.cmple(max).all())
+            .map(|(pos, entity)| (*pos, *entity))
+    }
+
+    /// Organisms no further than `radius` voxels from `center`
+    pub fn in_radius(
+        &self,
+        center: IVec3,
+        radius: u32,
+    ) -> impl Iterator<Item = (IVec3, Entity)> + '_ {
+        let radius = radius as i32;
+        self.in_box(center - IVec3::splat(radius), center + IVec3::splat(radius))
+            .filter(move |(pos, _)| (*pos - center).length_squared() <= radius * radius)
+    }
+}
+
+pub fn unindex_removed_organisms_system(
+    mut index: ResMut<OrganismIndex>,
+    mut removed: RemovedComponents<HasPosition>,
+) {
+    removed.read().for_each(|entity| {
+        index.remove(entity);
+    });
+}
+
+/// Covers newly spawned organisms as well as moved ones
+pub fn index_moved_organisms_system(
+    mut index: ResMut<OrganismIndex>,
+    query: Query<(Entity, &HasPosition), Changed<HasPosition>>,
+) {
+    query.for_each(|(entity, HasPosition { pos })| {
+        if index.position(entity) != Some(*pos) {
+            if let Some(replaced) = index.insert(entity, *pos) {
+                warn!("{:?} and {:?} are both at {}", replaced, entity, pos);
+            }
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::organism_index::*;
+
+    #[test]
+    fn queries_cross_chunk_borders() {
+        let mut world = World::new();
+        let mut index = OrganismIndex::default();
+        let positions = [
+            IVec3::new(31, 0, 0),
+            IVec3::new(32, 0, 0),
+            IVec3::new(-1, 0, 0),
+            IVec3::new(40, 0, 0),
+        ];
+        positions.iter().for_each(|pos| {
+            index.insert(world.spawn_empty().id(), *pos);
+        });
+
+        let mut near: Vec<IVec3> = index
+            .in_radius(IVec3::new(31, 0, 0), 1)
+            .map(|(pos, _)| pos)
+            .collect();
+        near.sort_by_key(|pos| pos.x);
+        assert_eq!(near, vec![IVec3::new(31, 0, 0), IVec3::new(32, 0, 0)]);
+        assert_eq!(index.in_box(IVec3::splat(-1), IVec3::splat(0)).count(), 1);
+    }
+
+    #[test]
+    fn moving_and_removing_keeps_one_entry_per_organism() {
+        let mut world = World::new();
+        let mut index = OrganismIndex::default();
+        let organism = world.spawn_empty().id();
+        index.insert(organism, IVec3::ZERO);
+        index.insert(organism, IVec3::splat(100));
+
+        assert_eq!(index.get(IVec3::ZERO), None);
+        assert_eq!(index.get(IVec3::splat(100)), Some(organism));
+        assert_eq!(index.len(), 1);
+        assert_eq!(index.remove(organism), Some(IVec3::splat(100)));
+        assert!(index.is_empty());
+    }
+}
@@ -1,5 +1,6 @@
 use crate::actions::Actions;
 use crate::core_components::*;
+use crate::species::SpeciesRegistry;
 use crate::voxel_painting::get_surface_air_voxel;
 use crate::GameState;
 use bevy::prelude::*;
@@ -29,28 +30,38 @@ fn spawn_player(
     query: Query<&Player>,
 ) {
     if query.is_empty() {
-        commands
-            .spawn((
-                SpriteBundle {
-                    transform: Transform::from_translation(Vec3::new(0., 200., 1.)),
-                    ..Default::default()
-                },
-                PlayerInventory::new(),
-            ))
-            .insert(Player);
+        spawn_player_entity(&mut commands, PlayerInventory::new());
 
         cam_transform.single_mut().translation = Vec3::new(0., 200., 1.);
     }
 }
 
+pub fn spawn_player_entity(commands: &mut Commands, inventory: PlayerInventory) -> Entity {
+    commands
+        .spawn((
+            SpriteBundle {
+                transform: Transform::from_translation(Vec3::new(0., 200., 1.)),
+                ..Default::default()
+            },
+            inventory,
+        ))
+        .insert(Player)
+        .id()
+}
+
 fn player_click(
     mut commands: Commands,
     voxel_world: VoxelWorld,
     actions: Res<Actions>,
+    species_registry: Res<SpeciesRegistry>,
     player_query: Query<(Entity, &Transform), With<Player>>,
     cam_query: Query<&Transform, (With<VoxelWorldCamera>, Without<Player>)>,
 ) {
     if actions.left_click_crosshair {
+        let Some(wheat) = species_registry.by_name("Wheat") else {
+            warn!("no Wheat species is defined; nothing to plant");
+            return;
+        };
         if let Ok(cam_transform) = cam_query.get_single() {
             let click_direction = cam_transform.forward().normalize_or_zero();
             player_query.for_each(|(player_entity, player_transform)| {
@@ -62,7 +73,7 @@ fn player_click(
                     let _managed_id = commands.spawn((PlayerWantsToPaintVoxel {
                         player: player_entity,
                         pos: voxel_pos,
-                        paint_as: PaintableResources::SeedCrop(Species::Wheat),
+                        paint_as: PaintableResources::SeedCrop(wheat),
                     },));
                     debug!("player painted voxel at {}", voxel_pos);
                 } else {
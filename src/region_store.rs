@@ -0,0 +1,344 @@
+use crate::organism_index::OrganismIndex;
+use crate::save_load::{SaveError, SavedVoxel};
+use crate::voxel_access::{VoxelAccess, VoxelGrid};
+use bevy::prelude::*;
+use bevy::utils::{HashMap, HashSet};
+use bevy_voxel_world::prelude::*;
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::io::ErrorKind;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, RwLock};
+
+/// Chunks along each side of a region. A region file holds the edits to every
+/// chunk stacked in its 16×16 chunk columns.
+pub const REGION_SIZE: i32 = 16;
+
+#[derive(Serialize, Deserialize, Default, Debug)]
+struct SavedRegion {
+    voxels: Vec<SavedVoxel>,
+}
+
+#[derive(Default, Debug)]
+struct Region {
+    /// Shared with the chunks' voxel lookups, see `RegionStore::chunk_edits`
+    chunks: HashMap<IVec3, Arc<VoxelGrid>>,
+    spawned_chunks: HashSet<IVec3>,
+    /// Has edits that aren't in its file yet
+    dirty: bool,
+}
+
+#[derive(Debug)]
+struct Regions {
+    dir: PathBuf,
+    loaded: HashMap<IVec2, Region>,
+    /// Chunks the `VoxelWorld` has been given voxels for with `set_voxel`. It keeps
+    /// those for as long as the world exists, see `RegionStore`, so they have to be
+    /// set again when another save is loaded. Only edits made while playing get
+    /// there; the rest reach chunks through their voxel lookup as they generate.
+    set_in_world: HashSet<IVec3>,
+}
+
+/// Every voxel the game has changed from what the terrain generator made, kept in
+/// one file per region under `dir` rather than in memory. A region is read when
+/// one of its chunks generates or is edited, and written and dropped once none of
+/// its chunks are spawned. Chunks are generated with their edits through the
+/// voxel lookup, on other threads, hence the lock.
+/// Edits made while playing still stay in memory until the world is left: they go
+/// through `VoxelWorld::set_voxel` so the chunk remeshes, and bevy_voxel_world
+/// 0.3 keeps every voxel set that way with no means of dropping them.
+/// `dir` only holds the world being played; saving copies it next to the save slot.
+#[derive(Resource, Clone, Debug)]
+pub struct RegionStore(Arc<RwLock<Regions>>);
+
+impl Regions {
+    fn region_path(&self, region: IVec2) -> PathBuf {
+        self.dir.join(format!("r.{}.{}.ron", region.x, region.y))
+    }
+
+    /// The region, read from its file if it isn't loaded yet
+    fn region(&mut self, region: IVec2) -> &mut Region {
+        let path = self.region_path(region);
+        self.loaded.entry(region).or_insert_with(|| {
+            let edits = read_region(&path).unwrap_or_else(|error| {
+                error!("Failed to read {}: {error}", path.display());
+                VoxelGrid::default()
+            });
+            let mut chunks: HashMap<IVec3, VoxelGrid> = HashMap::new();
+            edits.into_iter().for_each(|(pos, voxel)| {
+                chunks
+                    .entry(OrganismIndex::chunk_of(pos))
+                    .or_default()
+                    .insert(pos, voxel);
+            });
+            Region {
+                chunks: chunks
+                    .into_iter()
+                    .map(|(chunk_key, edits)| (chunk_key, Arc::new(edits)))
+                    .collect(),
+                ..default()
+            }
+        })
+    }
+
+    fn chunk_edits(&mut self, chunk_key: IVec3) -> Arc<VoxelGrid> {
+        self.region(RegionStore::region_of(chunk_key))
+            .chunks
+            .get(&chunk_key)
+            .cloned()
+            .unwrap_or_default()
+    }
+
+    fn write_region(&mut self, key: IVec2) -> Result<(), SaveError> {
+        let path = self.region_path(key);
+        let Some(region) = self.loaded.get_mut(&key).filter(|region| region.dirty) else {
+            return Ok(());
+        };
+        let voxels: Vec<SavedVoxel> = region
+            .chunks
+            .values()
+            .flat_map(|edits| edits.iter())
+            .map(|(pos, voxel)| SavedVoxel::new(*pos, *voxel))
+            .collect();
+        if voxels.is_empty() {
+            if let Err(error) = fs::remove_file(&path) {
+                if error.kind() != ErrorKind::NotFound {
+                    return Err(error.into());
+                }
+            }
+        } else {
+            fs::create_dir_all(&self.dir)?;
+            fs::write(&path, ron::ser::to_string(&SavedRegion { voxels })?)?;
+        }
+        region.dirty = false;
+        Ok(())
+    }
+
+    fn flush_all(&mut self) -> Result<(), SaveError> {
+        let keys: Vec<IVec2> = self.loaded.keys().copied().collect();
+        keys.into_iter().try_for_each(|key| self.write_region(key))
+    }
+}
+
+impl RegionStore {
+    /// Starts out with no edits, removing any region files left in `dir`
+    pub fn new(dir: PathBuf) -> Self {
+        if let Err(error) = remove_dir(&dir) {
+            error!("Failed to clear {}: {error}", dir.display());
+        }
+        RegionStore(Arc::new(RwLock::new(Regions {
+            dir,
+            loaded: HashMap::new(),
+            set_in_world: HashSet::new(),
+        })))
+    }
+
+    pub fn region_of(chunk_key: IVec3) -> IVec2 {
+        chunk_key.xz().div_euclid(IVec2::splat(REGION_SIZE))
+    }
+
+    /// The edits to the chunk, for its voxel lookup while it generates
+    pub fn chunk_edits(&self, chunk_key: IVec3) -> Arc<VoxelGrid> {
+        self.0.write().unwrap().chunk_edits(chunk_key)
+    }
+
+    /// An edit the `VoxelWorld` is being given too. `WorldVoxel::Unset` puts the
+    /// generated voxel back, so it is no longer an edit.
+    pub fn record(&self, pos: IVec3, voxel: WorldVoxel) {
+        let chunk_key = OrganismIndex::chunk_of(pos);
+        let mut regions = self.0.write().unwrap();
+        regions.set_in_world.insert(chunk_key);
+        let region = regions.region(Self::region_of(chunk_key));
+        let edits = Arc::make_mut(region.chunks.entry(chunk_key).or_default());
+        if voxel == WorldVoxel::Unset {
+            edits.remove(&pos);
+        } else {
+            edits.set_voxel(pos, voxel);
+        }
+        region.dirty = true;
+    }
+
+    pub fn chunk_spawned(&self, chunk_key: IVec3) {
+        let mut regions = self.0.write().unwrap();
+        let region = regions.region(Self::region_of(chunk_key));
+        region.spawned_chunks.insert(chunk_key);
+    }
+
+    pub fn chunk_despawned(&self, chunk_key: IVec3) {
+        let mut regions = self.0.write().unwrap();
+        if let Some(region) = regions.loaded.get_mut(&Self::region_of(chunk_key)) {
+            region.spawned_chunks.remove(&chunk_key);
+        }
+    }
+
+    /// Writes out and drops the regions that have none of their chunks spawned
+    pub fn flush_idle(&self) {
+        let mut regions = self.0.write().unwrap();
+        let idle: Vec<IVec2> = regions
+            .loaded
+            .iter()
+            .filter(|(_, region)| region.spawned_chunks.is_empty())
+            .map(|(key, _)| *key)
+            .collect();
+        idle.into_iter().for_each(|key| {
+            if let Err(error) = regions.write_region(key) {
+                error!(
+                    "Failed to write {}: {error}",
+                    regions.region_path(key).display()
+                );
+            }
+            regions.loaded.remove(&key);
+        });
+    }
+
+    /// Copies every region file to `dir`, replacing whatever was there
+    pub fn copy_to(&self, dir: &Path) -> Result<(), SaveError> {
+        let mut regions = self.0.write().unwrap();
+        regions.flush_all()?;
+        remove_dir(dir)?;
+        copy_dir(&regions.dir, dir)
+    }
+
+    /// Replaces every edit with the region files in `dir`. Returns the voxels the
+    /// `VoxelWorld` has to be given, for the chunks that are spawned or that it
+    /// has been given voxels for: the old edits go back to `WorldVoxel::Unset`,
+    /// then the new edits are set. Other chunks get the new edits as they generate.
+    pub fn replace_from(&self, dir: &Path) -> Result<Vec<(IVec3, WorldVoxel)>, SaveError> {
+        let mut regions = self.0.write().unwrap();
+        let spawned_chunks: Vec<IVec3> = regions
+            .loaded
+            .values()
+            .flat_map(|region| region.spawned_chunks.iter().copied())
+            .collect();
+        let affected: HashSet<IVec3> = regions
+            .set_in_world
+            .iter()
+            .copied()
+            .chain(spawned_chunks.iter().copied())
+            .collect();
+        let mut changes: Vec<(IVec3, WorldVoxel)> = affected
+            .iter()
+            .flat_map(|chunk_key| {
+                regions
+                    .chunk_edits(*chunk_key)
+                    .keys()
+                    .map(|pos| (*pos, WorldVoxel::Unset))
+                    .collect::<Vec<_>>()
+            })
+            .collect();
+
+        regions.loaded.clear();
+        remove_dir(&regions.dir)?;
+        copy_dir(dir, &regions.dir)?;
+
+        affected.iter().for_each(|chunk_key| {
+            let edits = regions.chunk_edits(*chunk_key);
+            if !edits.is_empty() {
+                regions.set_in_world.insert(*chunk_key);
+            }
+            changes.extend(edits.iter().map(|(pos, voxel)| (*pos, *voxel)));
+        });
+        spawned_chunks.into_iter().for_each(|chunk_key| {
+            let region = regions.region(Self::region_of(chunk_key));
+            region.spawned_chunks.insert(chunk_key);
+        });
+        Ok(changes)
+    }
+}
+
+fn read_region(path: &Path) -> Result<VoxelGrid, SaveError> {
+    let contents = match fs::read(path) {
+        Ok(contents) => contents,
+        // No file means nothing in the region has been edited
+        Err(error) if error.kind() == ErrorKind::NotFound => return Ok(VoxelGrid::default()),
+        Err(error) => return Err(error.into()),
+    };
+    Ok(ron::de::from_bytes::<SavedRegion>(&contents)?
+        .voxels
+        .into_iter()
+        .map(SavedVoxel::into_voxel)
+        .collect())
+}
+
+fn remove_dir(dir: &Path) -> Result<(), SaveError> {
+    match fs::remove_dir_all(dir) {
+        Err(error) if error.kind() != ErrorKind::NotFound => Err(error.into()),
+        _ => Ok(()),
+    }
+}
+
+/// Copies the files directly in `from`, if it exists, into a new directory `to`
+fn copy_dir(from: &Path, to: &Path) -> Result<(), SaveError> {
+    fs::create_dir_all(to)?;
+    let mut entries = match fs::read_dir(from) {
+        Ok(entries) => entries,
+        Err(error) if error.kind() == ErrorKind::NotFound => return Ok(()),
+        Err(error) => return Err(error.into()),
+    };
+    entries.try_for_each(|entry| {
+        let entry = entry?;
+        fs::copy(entry.path(), to.join(entry.file_name()))?;
+        Ok(())
+    })
+}
+
+/// Keeps track of which chunks are spawned, and writes regions out once their
+/// chunks have all despawned
+pub fn region_chunks_system(
+    region_store: Res<RegionStore>,
+    mut chunk_spawns: EventReader<ChunkWillSpawn>,
+    mut chunk_despawns: EventReader<ChunkWillDespawn>,
+) {
+    chunk_spawns
+        .read()
+        .for_each(|chunk_spawn| region_store.chunk_spawned(chunk_spawn.chunk_key));
+    let mut despawned = false;
+    chunk_despawns.read().for_each(|chunk_despawn| {
+        region_store.chunk_despawned(chunk_despawn.chunk_key);
+        despawned = true;
+    });
+    if despawned {
+        region_store.flush_idle();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::block_types::BlockType;
+    use crate::organism_index::CHUNK_SIZE;
+    use crate::region_store::*;
+
+    #[test]
+    fn edits_survive_their_region_unloading() {
+        let dir = std::env::temp_dir().join(format!("region_store_test_{}", std::process::id()));
+        let store = RegionStore::new(dir.clone());
+        let chunk_key = IVec3::new(-1, 0, 20);
+        let pos = chunk_key * CHUNK_SIZE + IVec3::new(3, 4, 5);
+        let dirt = WorldVoxel::Solid(BlockType::Dirt as u8);
+
+        store.chunk_spawned(chunk_key);
+        store.record(pos, dirt);
+        store.record(pos + IVec3::Y, WorldVoxel::Air);
+        store.record(pos + IVec3::Y, WorldVoxel::Unset);
+        // The region stays in memory while one of its chunks is spawned
+        store.flush_idle();
+        assert!(!dir.join("r.-1.1.ron").exists());
+
+        store.chunk_despawned(chunk_key);
+        store.flush_idle();
+        assert!(store.0.read().unwrap().loaded.is_empty());
+        assert!(dir.join("r.-1.1.ron").exists());
+        assert_eq!(store.chunk_edits(chunk_key).get_voxel(pos), dirt);
+        assert_eq!(store.chunk_edits(chunk_key).iter().count(), 1);
+        assert!(store.chunk_edits(IVec3::new(0, 0, 20)).is_empty());
+
+        // Loading another set of regions undoes the edits the voxel world was given
+        let other_dir = dir.with_extension("other");
+        assert_eq!(
+            store.replace_from(&other_dir).unwrap(),
+            vec![(pos, WorldVoxel::Unset)]
+        );
+        assert!(store.chunk_edits(chunk_key).is_empty());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
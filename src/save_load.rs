@@ -0,0 +1,580 @@
+use crate::actions::Actions;
+use crate::core_components::*;
+use crate::day_night::TimeOfDay;
+use crate::map_setup::TerrainGenerator;
+use crate::player::{spawn_player_entity, Player};
+use crate::region_store::{region_chunks_system, RegionStore};
+use crate::soil::SoilFertility;
+use crate::spawner::spawn_organism;
+use crate::species::SpeciesRegistry;
+use crate::timer::GameTimer;
+use crate::ui_handler::HudMessage;
+use crate::voxel_painting::SetVoxel;
+use crate::weather::Weather;
+use crate::wild_vegetation::PopulatedChunks;
+use crate::world_gen::WorldGenSettings;
+use crate::GameState;
+use bevy::prelude::*;
+use bevy::utils::HashMap;
+use bevy_voxel_world::prelude::*;
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::PathBuf;
+use thiserror::Error;
+
+/// Bump this when the save format changes in a way older saves can't be read with.
+/// Fields added later should use `#[serde(default)]` so that older saves still load.
+pub const SAVE_VERSION: u32 = 1;
+pub const SAVE_SLOTS: u8 = 3;
+const SAVE_DIR: &str = "saves";
+
+pub struct SaveLoadPlugin;
+
+/// This plugin writes the game to, and reads it back from, one of several save slots.
+/// Saving and loading are only possible during the State `GameState::Playing`
+impl Plugin for SaveLoadPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<CurrentSaveSlot>()
+            .insert_resource(RegionStore::new(PathBuf::from(SAVE_DIR).join("regions")))
+            .add_systems(
+                Update,
+                (
+                    record_voxel_edits_system,
+                    region_chunks_system,
+                    select_save_slot_system,
+                    save_game_system,
+                    load_game_system,
+                    apply_pending_load_system.run_if(resource_exists::<PendingLoad>()),
+                )
+                    .chain()
+                    .run_if(in_state(GameState::Playing)),
+            );
+    }
+}
+
+#[derive(Resource, Default, Clone, Copy, Debug)]
+pub struct CurrentSaveSlot(pub u8);
+
+/// Set to have the given slot loaded on the next frame in `GameState::Playing`
+#[derive(Resource, Clone, Copy, Debug)]
+pub struct PendingLoad(pub u8);
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct SaveGame {
+    pub version: u32,
+    pub game_timer: Option<GameTimer>,
+    /// Fraction of the day that had passed, see `TimeOfDay`
+    #[serde(default)]
+    pub time_of_day: Option<f32>,
+    #[serde(default)]
+    pub weather: Option<Weather>,
+    pub players: Vec<SavedPlayer>,
+    pub organisms: Vec<SavedOrganism>,
+    /// Only in saves from before the voxel edits were kept in region files next to
+    /// the save, see `RegionStore`
+    #[serde(default)]
+    pub voxels: Vec<SavedVoxel>,
+    /// Chunks that have had their wild vegetation, see `PopulatedChunks`
+    #[serde(default)]
+    pub populated_chunks: Vec<[i32; 3]>,
+    /// Only chunks whose fertility has changed, see `SoilFertility`
+    #[serde(default)]
+    pub soil_fertility: Vec<SavedSoilChunk>,
+    /// What the terrain was generated from; saves from before this was recorded don't have it
+    #[serde(default)]
+    pub world_gen: Option<WorldGenSettings>,
+}
+
+/// Fertility of every column in the chunk, row by row
+#[derive(Serialize, Deserialize, Debug)]
+pub struct SavedSoilChunk {
+    pub chunk: [i32; 2],
+    pub fertility: Vec<u32>,
+}
+
+/// `id` is only meaningful within one save file; organisms refer to their owner by it
+#[derive(Serialize, Deserialize, Debug)]
+pub struct SavedPlayer {
+    pub id: u32,
+    pub inventory: Vec<(SavedFarmResource, u32)>,
+}
+
+/// Species are saved by name, since `Species` ids depend on which species files are present
+#[derive(Serialize, Deserialize, Debug)]
+pub enum SavedFarmResource {
+    FoodValue(String),
+    Seeds(String),
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct SavedOrganism {
+    pub species: String,
+    pub phase: LifePhase,
+    pub pos: [i32; 3],
+    pub water: Water,
+    pub soil: Soil,
+    pub generations: u32,
+    pub germination_timer: Option<f32>,
+    pub mature_age_timer: Option<f32>,
+    pub decay_timer: Option<f32>,
+    pub owner: Option<u32>,
+    #[serde(default)]
+    pub health: Option<f32>,
+    #[serde(default)]
+    pub lifespan: Option<u32>,
+    #[serde(default)]
+    pub tree: Option<SavedTree>,
+    /// Failed cross-pollination attempts, see `PollinationFailed`
+    #[serde(default)]
+    pub pollination_attempts: Option<u32>,
+    /// Seconds since the organism last had its turn, see `LastProcessed`; the
+    /// time itself is on the clock of the run it was saved in
+    #[serde(default)]
+    pub since_processed: Option<f32>,
+}
+
+/// See `TreeVoxels`
+#[derive(Serialize, Deserialize, Debug)]
+pub struct SavedTree {
+    pub trunk: Vec<[i32; 3]>,
+    pub canopy: Vec<[i32; 3]>,
+    pub fruit: Vec<[i32; 3]>,
+    pub size: (u8, u8),
+}
+
+impl From<&TreeVoxels> for SavedTree {
+    fn from(tree: &TreeVoxels) -> Self {
+        let save = |voxels: &Vec<IVec3>| voxels.iter().map(|pos| pos.to_array()).collect();
+        SavedTree {
+            trunk: save(&tree.trunk),
+            canopy: save(&tree.canopy),
+            fruit: save(&tree.fruit),
+            size: tree.size,
+        }
+    }
+}
+
+impl From<SavedTree> for TreeVoxels {
+    fn from(tree: SavedTree) -> Self {
+        let load = |voxels: Vec<[i32; 3]>| voxels.into_iter().map(IVec3::from_array).collect();
+        TreeVoxels {
+            trunk: load(tree.trunk),
+            canopy: load(tree.canopy),
+            fruit: load(tree.fruit),
+            size: tree.size,
+        }
+    }
+}
+
+/// `block` is `None` for air
+#[derive(Serialize, Deserialize, Debug)]
+pub struct SavedVoxel {
+    pub pos: [i32; 3],
+    pub block: Option<u8>,
+}
+
+impl SavedVoxel {
+    pub fn new(pos: IVec3, voxel: WorldVoxel) -> Self {
+        SavedVoxel {
+            pos: pos.to_array(),
+            block: match voxel {
+                WorldVoxel::Solid(block) => Some(block),
+                _ => None,
+            },
+        }
+    }
+
+    pub fn into_voxel(self) -> (IVec3, WorldVoxel) {
+        let voxel = self.block.map_or(WorldVoxel::Air, WorldVoxel::Solid);
+        (IVec3::from_array(self.pos), voxel)
+    }
+}
+
+#[derive(Debug, Error)]
+pub enum SaveError {
+    #[error("could not access save file: {0}")]
+    Io(#[from] std::io::Error),
+    #[error("could not write save file: {0}")]
+    Serialize(#[from] ron::Error),
+    #[error("could not parse save file: {0}")]
+    Deserialize(#[from] ron::error::SpannedError),
+    #[error("save file version {0} is newer than this game supports ({SAVE_VERSION})")]
+    UnsupportedVersion(u32),
+    #[error("save file refers to unknown species '{0}'")]
+    UnknownSpecies(String),
+    #[error("save file is from a different world; load it from the menu instead")]
+    DifferentWorld,
+}
+
+pub fn save_slot_path(slot: u8) -> PathBuf {
+    PathBuf::from(SAVE_DIR).join(format!("slot_{}.ron", slot))
+}
+
+/// The region files of the voxel edits saved in the slot
+fn slot_regions_path(slot: u8) -> PathBuf {
+    PathBuf::from(SAVE_DIR).join(format!("slot_{}_regions", slot))
+}
+
+/// The slot that was saved to most recently, if any
+pub fn latest_save_slot() -> Option<u8> {
+    (0..SAVE_SLOTS)
+        .filter_map(|slot| {
+            let modified = fs::metadata(save_slot_path(slot)).ok()?.modified().ok()?;
+            Some((slot, modified))
+        })
+        .max_by_key(|(_, modified)| *modified)
+        .map(|(slot, _)| slot)
+}
+
+fn write_save(slot: u8, save: &SaveGame) -> Result<(), SaveError> {
+    let contents = ron::ser::to_string_pretty(save, ron::ser::PrettyConfig::default())?;
+    fs::create_dir_all(SAVE_DIR)?;
+    fs::write(save_slot_path(slot), contents)?;
+    Ok(())
+}
+
+fn read_save(slot: u8) -> Result<SaveGame, SaveError> {
+    let save = ron::de::from_bytes::<SaveGame>(&fs::read(save_slot_path(slot))?)?;
+    if save.version > SAVE_VERSION {
+        return Err(SaveError::UnsupportedVersion(save.version));
+    }
+    Ok(save)
+}
+
+/// The world the slot was saved in, to generate it again before loading the slot
+pub fn saved_world_gen(slot: u8) -> Option<WorldGenSettings> {
+    read_save(slot).ok()?.world_gen
+}
+
+fn record_voxel_edits_system(
+    region_store: Res<RegionStore>,
+    mut voxel_edits: EventReader<SetVoxel>,
+) {
+    voxel_edits
+        .read()
+        .for_each(|edit| region_store.record(edit.pos, edit.voxel));
+}
+
+fn select_save_slot_system(actions: Res<Actions>, mut current_slot: ResMut<CurrentSaveSlot>) {
+    if let Some(slot) = actions.select_save_slot {
+        current_slot.0 = slot;
+        info!("selected save slot {}", slot + 1);
+    }
+}
+
+#[allow(clippy::too_many_arguments)]
+fn save_game_system(
+    actions: Res<Actions>,
+    current_slot: Res<CurrentSaveSlot>,
+    species_registry: Res<SpeciesRegistry>,
+    region_store: Res<RegionStore>,
+    time_of_day: Res<TimeOfDay>,
+    weather: Res<Weather>,
+    populated_chunks: Res<PopulatedChunks>,
+    fertility: Res<SoilFertility>,
+    world_gen_settings: Res<WorldGenSettings>,
+    time: Res<Time>,
+    timer_query: Query<&GameTimer>,
+    player_query: Query<(Entity, &PlayerInventory), With<Player>>,
+    organism_query: Query<(
+        &Species,
+        AnyPhase,
+        &HasPosition,
+        &Water,
+        &Soil,
+        &Generations,
+        Option<&GerminationTimer>,
+        Option<&MatureAgeTimer>,
+        Option<&DecayTimer>,
+        Option<&OwnedBy>,
+        Option<&Health>,
+        Option<&Lifespan>,
+        Option<&TreeVoxels>,
+        Option<&PollinationFailed>,
+        Option<&LastProcessed>,
+    )>,
+) {
+    if !actions.save_game {
+        return;
+    }
+    let species_name = |species: &Species| species_registry.get(*species).name.clone();
+    let player_ids: HashMap<Entity, u32> = player_query
+        .iter()
+        .enumerate()
+        .map(|(ix, (player, _))| (player, ix as u32))
+        .collect();
+    let save = SaveGame {
+        version: SAVE_VERSION,
+        game_timer: timer_query.get_single().ok().cloned(),
+        time_of_day: Some(time_of_day.fraction),
+        weather: Some(weather.clone()),
+        players: player_query
+            .iter()
+            .map(|(player, inventory)| SavedPlayer {
+                id: player_ids[&player],
+                inventory: inventory
+                    .resources
+                    .iter()
+                    .map(|(resource, amount)| {
+                        let saved_resource = match resource {
+                            FarmResource::FoodValue(species) => {
+                                SavedFarmResource::FoodValue(species_name(species))
+                            }
+                            FarmResource::Seeds(species) => {
+                                SavedFarmResource::Seeds(species_name(species))
+                            }
+                        };
+                        (saved_resource, *amount)
+                    })
+                    .collect(),
+            })
+            .collect(),
+        organisms: organism_query
+            .iter()
+            .map(
+                |(
+                    species,
+                    phase,
+                    HasPosition { pos },
+                    water,
+                    soil,
+                    generations,
+                    germination_timer,
+                    mature_age_timer,
+                    decay_timer,
+                    owned_by,
+                    health,
+                    lifespan,
+                    tree,
+                    pollination_failed,
+                    last_processed,
+                )| SavedOrganism {
+                    species: species_name(species),
+                    phase: LifePhase::from_components(phase),
+                    pos: pos.to_array(),
+                    water: water.clone(),
+                    soil: soil.clone(),
+                    generations: generations.0,
+                    germination_timer: germination_timer.map(|timer| timer.0),
+                    mature_age_timer: mature_age_timer.map(|timer| timer.0),
+                    decay_timer: decay_timer.map(|timer| timer.0),
+                    owner: owned_by.and_then(|OwnedBy { owner }| player_ids.get(owner).copied()),
+                    health: health.map(|health| health.0),
+                    lifespan: lifespan.map(|lifespan| lifespan.0),
+                    tree: tree.map(SavedTree::from),
+                    pollination_attempts: pollination_failed.map(|failed| failed.attempts),
+                    since_processed: last_processed
+                        .and_then(|last_processed| last_processed.0)
+                        .map(|last| (time.elapsed_seconds_f64() - last) as f32),
+                },
+            )
+            .collect(),
+        voxels: Vec::new(),
+        populated_chunks: populated_chunks
+            .iter()
+            .map(|chunk_key| chunk_key.to_array())
+            .collect(),
+        soil_fertility: fertility
+            .chunks()
+            .map(|(chunk, fertility)| SavedSoilChunk {
+                chunk: chunk.to_array(),
+                fertility: fertility.clone(),
+            })
+            .collect(),
+        world_gen: Some(world_gen_settings.clone()),
+    };
+    match region_store
+        .copy_to(&slot_regions_path(current_slot.0))
+        .and_then(|()| write_save(current_slot.0, &save))
+    {
+        Ok(()) => info!("saved game to slot {}", current_slot.0 + 1),
+        Err(error) => error!("Failed to save game: {error}"),
+    }
+}
+
+fn load_game_system(
+    mut commands: Commands,
+    actions: Res<Actions>,
+    current_slot: Res<CurrentSaveSlot>,
+) {
+    if actions.load_game {
+        commands.insert_resource(PendingLoad(current_slot.0));
+    }
+}
+
+/// Replaces the players, organisms, timer and voxel edits with those from the save file.
+/// Players are spawned first so that `OwnedBy` can be pointed at their new entities.
+#[allow(clippy::too_many_arguments)]
+fn apply_pending_load_system(
+    mut commands: Commands,
+    pending_load: Res<PendingLoad>,
+    species_registry: Res<SpeciesRegistry>,
+    region_store: Res<RegionStore>,
+    mut voxel_world: VoxelWorld,
+    terrain: Res<TerrainGenerator>,
+    mut time_of_day: ResMut<TimeOfDay>,
+    mut weather: ResMut<Weather>,
+    mut populated_chunks: ResMut<PopulatedChunks>,
+    mut fertility: ResMut<SoilFertility>,
+    world_gen_settings: Res<WorldGenSettings>,
+    time: Res<Time>,
+    mut hud_messages: EventWriter<HudMessage>,
+    mut timer_query: Query<&mut GameTimer>,
+    player_query: Query<Entity, With<Player>>,
+    stale_query: Query<Entity, Or<(With<Species>, With<CollectResource>)>>,
+) {
+    commands.remove_resource::<PendingLoad>();
+    let save = match read_save(pending_load.0).and_then(|save| {
+        // The terrain is only generated once, so it has to be this save's already
+        match &save.world_gen {
+            Some(world_gen) if *world_gen != *world_gen_settings => Err(SaveError::DifferentWorld),
+            _ => Ok(save),
+        }
+    }) {
+        Ok(save) => save,
+        Err(error) => {
+            let message = format!("Failed to load slot {}: {error}", pending_load.0 + 1);
+            error!("{message}");
+            hud_messages.send(HudMessage(message));
+            return;
+        }
+    };
+    let species_id = |name: &String| {
+        species_registry
+            .by_name(name)
+            .ok_or_else(|| SaveError::UnknownSpecies(name.clone()))
+    };
+    // Resolve everything up front, so a bad save leaves the current game untouched
+    let players = save
+        .players
+        .iter()
+        .map(|saved_player| {
+            let resources = saved_player
+                .inventory
+                .iter()
+                .map(|(saved_resource, amount)| {
+                    let resource = match saved_resource {
+                        SavedFarmResource::FoodValue(name) => {
+                            FarmResource::FoodValue(species_id(name)?)
+                        }
+                        SavedFarmResource::Seeds(name) => FarmResource::Seeds(species_id(name)?),
+                    };
+                    Ok((resource, *amount))
+                })
+                .collect::<Result<HashMap<_, _>, SaveError>>()?;
+            Ok((saved_player.id, PlayerInventory { resources }))
+        })
+        .collect::<Result<Vec<_>, SaveError>>();
+    let organism_species = save
+        .organisms
+        .iter()
+        .map(|organism| species_id(&organism.species))
+        .collect::<Result<Vec<_>, SaveError>>();
+    let (players, organism_species) = match (players, organism_species) {
+        (Ok(players), Ok(organism_species)) => (players, organism_species),
+        (Err(error), _) | (_, Err(error)) => {
+            let message = format!("Failed to load slot {}: {error}", pending_load.0 + 1);
+            error!("{message}");
+            hud_messages.send(HudMessage(message));
+            return;
+        }
+    };
+
+    player_query
+        .iter()
+        .chain(stale_query.iter())
+        .for_each(|entity| commands.entity(entity).despawn());
+
+    let player_entities: HashMap<u32, Entity> = players
+        .into_iter()
+        .map(|(id, inventory)| (id, spawn_player_entity(&mut commands, inventory)))
+        .collect();
+
+    save.organisms
+        .into_iter()
+        .zip(organism_species)
+        .for_each(|(organism, species)| {
+            let entity = spawn_organism(
+                &mut commands,
+                species,
+                organism.phase,
+                IVec3::from_array(organism.pos),
+                organism
+                    .owner
+                    .and_then(|owner| player_entities.get(&owner).copied()),
+            );
+            let mut entity_commands = commands.entity(entity);
+            entity_commands
+                .insert((
+                    organism.water,
+                    organism.soil,
+                    Generations(organism.generations),
+                ))
+                .remove::<GerminationTimer>();
+            if let Some(timer) = organism.germination_timer {
+                entity_commands.insert(GerminationTimer(timer));
+            }
+            if let Some(timer) = organism.mature_age_timer {
+                entity_commands.insert(MatureAgeTimer(timer));
+            }
+            if let Some(timer) = organism.decay_timer {
+                entity_commands.insert(DecayTimer(timer));
+            }
+            if let Some(health) = organism.health {
+                entity_commands.insert(Health(health));
+            }
+            if let Some(lifespan) = organism.lifespan {
+                entity_commands.insert(Lifespan(lifespan));
+            }
+            if let Some(tree) = organism.tree {
+                entity_commands.insert(TreeVoxels::from(tree));
+            }
+            if let Some(attempts) = organism.pollination_attempts {
+                entity_commands.insert(PollinationFailed { attempts });
+            }
+            if let Some(since) = organism.since_processed {
+                entity_commands.insert(LastProcessed(Some(
+                    time.elapsed_seconds_f64() - since as f64,
+                )));
+            }
+        });
+
+    if let Some(saved_timer) = save.game_timer {
+        match timer_query.get_single_mut() {
+            Ok(mut timer) => *timer = saved_timer,
+            Err(_) => {
+                commands.spawn(saved_timer);
+            }
+        }
+    }
+
+    if let Some(fraction) = save.time_of_day {
+        time_of_day.fraction = fraction.rem_euclid(1.);
+    }
+    if let Some(saved_weather) = save.weather {
+        *weather = saved_weather;
+    }
+    populated_chunks.0 = save
+        .populated_chunks
+        .into_iter()
+        .map(IVec3::from_array)
+        .collect();
+    fertility.clear();
+    save.soil_fertility
+        .into_iter()
+        .for_each(|saved| fertility.insert_chunk(IVec2::from_array(saved.chunk), saved.fertility));
+
+    // Straight to the `VoxelWorld`, as these aren't new edits for the `RegionStore`
+    match region_store.replace_from(&slot_regions_path(pending_load.0)) {
+        Ok(changes) => changes
+            .into_iter()
+            .for_each(|(pos, voxel)| voxel_world.set_voxel(pos, terrain.or_generated(pos, voxel))),
+        Err(error) => error!("Failed to load voxel edits: {error}"),
+    }
+    save.voxels.into_iter().for_each(|saved| {
+        let (pos, voxel) = saved.into_voxel();
+        region_store.record(pos, voxel);
+        voxel_world.set_voxel(pos, voxel);
+    });
+    info!("loaded game from slot {}", pending_load.0 + 1);
+}
@@ -0,0 +1,38 @@
+use bevy::math::IVec2;
+use bevy::utils::HashMap;
+use std::sync::{Arc, RwLock};
+
+/// Values worked out for parts of the world, by their position on the map, shared
+/// with the threads chunks are generated on. Clones share the same values.
+#[derive(Debug)]
+pub struct SharedCache<T>(Arc<RwLock<HashMap<IVec2, Arc<T>>>>);
+
+impl<T> Default for SharedCache<T> {
+    fn default() -> Self {
+        SharedCache(Default::default())
+    }
+}
+
+impl<T> Clone for SharedCache<T> {
+    fn clone(&self) -> Self {
+        SharedCache(self.0.clone())
+    }
+}
+
+impl<T> SharedCache<T> {
+    /// The value at `key`, worked out with `compute` if there isn't one yet. That is
+    /// done without holding the lock, so other threads aren't held up by it; if one
+    /// of them got there first, its value is kept and returned instead.
+    pub fn get_or_insert_with(&self, key: IVec2, compute: impl FnOnce() -> T) -> Arc<T> {
+        if let Some(value) = self.0.read().unwrap().get(&key) {
+            return value.clone();
+        }
+        let value = Arc::new(compute());
+        self.0.write().unwrap().entry(key).or_insert(value).clone()
+    }
+
+    /// Drops the values whose key `keep` returns false for
+    pub fn retain(&self, mut keep: impl FnMut(IVec2) -> bool) {
+        self.0.write().unwrap().retain(|key, _| keep(*key));
+    }
+}
@@ -0,0 +1,133 @@
+use crate::world_gen::WorldGenSettings;
+use bevy::prelude::*;
+use bevy::utils::HashMap;
+use rand::{RngCore, SeedableRng};
+use rand_chacha::ChaCha8Rng;
+use serde::{Deserialize, Serialize};
+
+/// Derives all of the simulation's randomness from the `WorldSeed`, which is
+/// always the seed of the `WorldGenSettings`
+pub struct SimRngPlugin;
+
+impl Plugin for SimRngPlugin {
+    fn build(&self, app: &mut App) {
+        let seed = app
+            .world
+            .get_resource_or_insert_with(WorldGenSettings::default)
+            .seed;
+        app.insert_resource(WorldSeed(seed))
+            .insert_resource(SimRng::new(WorldSeed(seed)))
+            .add_systems(
+                PreUpdate,
+                (
+                    world_seed_system.run_if(resource_changed::<WorldGenSettings>()),
+                    reseed_sim_rng_system.run_if(resource_changed::<WorldSeed>()),
+                )
+                    .chain(),
+            );
+    }
+}
+
+/// The seed every random stream in the simulation is derived from, so the same
+/// seed and the same player inputs give the same farm. It is logged so runs can
+/// be reproduced.
+#[derive(Resource, Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct WorldSeed(pub u64);
+
+/// Every consumer of randomness draws from its own stream, so adding random
+/// draws in one place doesn't shift the numbers seen everywhere else
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum RngStream {
+    Terrain,
+    Weather,
+    Lifespans,
+    SeedSpreading,
+    WildVegetation,
+}
+
+impl WorldSeed {
+    pub fn rng(&self, stream: RngStream) -> ChaCha8Rng {
+        let mut rng = ChaCha8Rng::seed_from_u64(self.0);
+        rng.set_stream(stream as u64);
+        rng
+    }
+
+    /// A stream of its own for each chunk, so a chunk comes out the same
+    /// whichever order chunks are visited in
+    pub fn chunk_rng(&self, stream: RngStream, chunk_key: IVec3) -> ChaCha8Rng {
+        let mut seed = [0; 32];
+        seed[..8].copy_from_slice(&self.0.to_le_bytes());
+        chunk_key
+            .to_array()
+            .iter()
+            .enumerate()
+            .for_each(|(ix, coord)| {
+                seed[8 + 4 * ix..12 + 4 * ix].copy_from_slice(&coord.to_le_bytes())
+            });
+        let mut rng = ChaCha8Rng::from_seed(seed);
+        rng.set_stream(stream as u64);
+        rng
+    }
+
+    pub fn noise_seed(&self) -> u32 {
+        self.rng(RngStream::Terrain).next_u32()
+    }
+}
+
+/// The random streams in use; each one continues where it left off across frames
+#[derive(Resource, Debug)]
+pub struct SimRng {
+    seed: WorldSeed,
+    streams: HashMap<RngStream, ChaCha8Rng>,
+}
+
+impl SimRng {
+    pub fn new(seed: WorldSeed) -> Self {
+        SimRng {
+            seed,
+            streams: HashMap::new(),
+        }
+    }
+
+    pub fn stream(&mut self, stream: RngStream) -> &mut ChaCha8Rng {
+        let seed = self.seed;
+        self.streams
+            .entry(stream)
+            .or_insert_with(|| seed.rng(stream))
+    }
+}
+
+/// Follows the seed as it is changed in the menu, or by loading a save
+fn world_seed_system(settings: Res<WorldGenSettings>, mut world_seed: ResMut<WorldSeed>) {
+    world_seed.set_if_neq(WorldSeed(settings.seed));
+}
+
+fn reseed_sim_rng_system(world_seed: Res<WorldSeed>, mut sim_rng: ResMut<SimRng>) {
+    info!("world seed: {}", world_seed.0);
+    *sim_rng = SimRng::new(*world_seed);
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::sim_rng::*;
+    use rand::Rng;
+
+    #[test]
+    fn streams_are_reproducible_and_independent() {
+        let draws = |sim_rng: &mut SimRng, stream| -> Vec<u32> {
+            (0..4).map(|_| sim_rng.stream(stream).gen()).collect()
+        };
+        let mut first = SimRng::new(WorldSeed(42));
+        let mut second = SimRng::new(WorldSeed(42));
+        draws(&mut second, RngStream::Weather);
+
+        assert_eq!(
+            draws(&mut first, RngStream::Lifespans),
+            draws(&mut second, RngStream::Lifespans)
+        );
+        assert_ne!(
+            draws(&mut first, RngStream::Weather),
+            draws(&mut SimRng::new(WorldSeed(43)), RngStream::Weather)
+        );
+    }
+}
@@ -0,0 +1,124 @@
+use crate::core_components::*;
+use crate::lifecycles::LifeCycleSet;
+use crate::organism_index::CHUNK_SIZE;
+use bevy::prelude::*;
+use bevy::utils::HashMap;
+
+/// Fertility every column starts out with
+pub const INITIAL_FERTILITY: u32 = 100;
+/// Crops harvested from a column this fertile or better give their full yield
+pub const FULL_YIELD_FERTILITY: u32 = 100;
+pub const MAX_FERTILITY: u32 = 200;
+
+/// Keeps each organism's `Soil` in step with the fertility of the ground it grows in.
+/// Growing and fruiting draw from `SoilFertility`, and decay gives back to it.
+pub struct SoilPlugin;
+
+impl Plugin for SoilPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<SoilFertility>()
+            .add_systems(Update, soil_access_system.in_set(LifeCycleSet));
+    }
+}
+
+/// Fertility of each column of ground, by the column's `x` and `z`. Stored per
+/// chunk, and only for chunks where it has changed from `INITIAL_FERTILITY`.
+#[derive(Resource, Default, Clone, Debug)]
+pub struct SoilFertility {
+    chunks: HashMap<IVec2, Vec<u32>>,
+}
+
+impl SoilFertility {
+    fn chunk_and_index(column: IVec2) -> (IVec2, usize) {
+        let size = IVec2::splat(CHUNK_SIZE);
+        let local = column.rem_euclid(size);
+        (
+            column.div_euclid(size),
+            (local.x + local.y * CHUNK_SIZE) as usize,
+        )
+    }
+
+    pub fn get(&self, column: IVec2) -> u32 {
+        let (chunk, ix) = SoilFertility::chunk_and_index(column);
+        self.chunks
+            .get(&chunk)
+            .map_or(INITIAL_FERTILITY, |fertility| fertility[ix])
+    }
+
+    fn get_mut(&mut self, column: IVec2) -> &mut u32 {
+        let (chunk, ix) = SoilFertility::chunk_and_index(column);
+        &mut self
+            .chunks
+            .entry(chunk)
+            .or_insert_with(|| vec![INITIAL_FERTILITY; (CHUNK_SIZE * CHUNK_SIZE) as usize])[ix]
+    }
+
+    /// Takes up to `amount` from the column, returning how much there was to take
+    pub fn take(&mut self, column: IVec2, amount: u32) -> u32 {
+        if amount == 0 {
+            return 0;
+        }
+        let fertility = self.get_mut(column);
+        let taken = amount.min(*fertility);
+        *fertility -= taken;
+        taken
+    }
+
+    pub fn give(&mut self, column: IVec2, amount: u32) {
+        if amount > 0 {
+            let fertility = self.get_mut(column);
+            *fertility = (*fertility + amount).min(MAX_FERTILITY);
+        }
+    }
+
+    /// Scales a harvest from the column, from 0 on exhausted ground up to 1
+    pub fn yield_factor(&self, column: IVec2) -> f32 {
+        (self.get(column) as f32 / FULL_YIELD_FERTILITY as f32).min(1.)
+    }
+
+    pub fn chunks(&self) -> impl Iterator<Item = (&IVec2, &Vec<u32>)> {
+        self.chunks.iter()
+    }
+
+    /// Ignores chunks that don't have a value for every column
+    pub fn insert_chunk(&mut self, chunk: IVec2, fertility: Vec<u32>) {
+        if fertility.len() == (CHUNK_SIZE * CHUNK_SIZE) as usize {
+            self.chunks.insert(chunk, fertility);
+        }
+    }
+
+    pub fn clear(&mut self) {
+        self.chunks.clear();
+    }
+}
+
+/// Newly spawned organisms get the `Soil` of their column; the lifecycle
+/// systems keep it up to date as they draw from it
+fn soil_access_system(
+    fertility: Res<SoilFertility>,
+    mut query: Query<(&HasPosition, &mut Soil), Added<HasPosition>>,
+) {
+    query.for_each_mut(|(HasPosition { pos }, mut soil)| {
+        soil.0 = fertility.get(pos.xz());
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::soil::*;
+
+    #[test]
+    fn columns_deplete_and_recover_independently() {
+        let mut fertility = SoilFertility::default();
+        let column = IVec2::new(-1, 33);
+        assert_eq!(fertility.take(column, 30), 30);
+        assert_eq!(fertility.take(column, 100), INITIAL_FERTILITY - 30);
+        assert_eq!(fertility.get(column), 0);
+        assert_eq!(fertility.yield_factor(column), 0.);
+        assert_eq!(fertility.get(IVec2::new(0, 33)), INITIAL_FERTILITY);
+
+        fertility.give(column, 2 * MAX_FERTILITY);
+        assert_eq!(fertility.get(column), MAX_FERTILITY);
+        assert_eq!(fertility.yield_factor(column), 1.);
+    }
+}
@@ -10,21 +10,21 @@ pub fn spawn_organism(
     pos: IVec3,
     player_entity_opt: Option<Entity>,
 ) -> Entity {
-    let new_entity_id = commands
-        .spawn((
-            species.clone(),
-            phase.clone(),
-            HasPosition { pos },
-            Water(1),              // TODO: hardcoded for now, should be species-specific
-            Soil(1),               // TODO: hardcoded for now, should be species-specific
-            GerminationTimer(5.0), // TODO: hardcoded for now, should be species-specific
-            Generations(0),
-        ))
-        .id();
+    let mut entity_commands = commands.spawn((
+        species,
+        HasPosition { pos },
+        Water(1),              // TODO: hardcoded for now, should be species-specific
+        Soil(1),               // TODO: hardcoded for now, should be species-specific
+        GerminationTimer(5.0), // TODO: hardcoded for now, should be species-specific
+        Generations(0),
+        Health::default(),
+        LastProcessed::default(),
+    ));
+    phase.insert_into(&mut entity_commands);
     if let Some(player_entity) = player_entity_opt {
-        commands.entity(new_entity_id).insert(OwnedBy {
+        entity_commands.insert(OwnedBy {
             owner: player_entity,
         });
     }
-    new_entity_id
+    entity_commands.id()
 }
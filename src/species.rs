@@ -0,0 +1,426 @@
+use crate::biome::Biome;
+use crate::block_types::BlockType;
+use crate::core_components::*;
+use crate::loading::SpeciesAssets;
+use bevy::asset::io::Reader;
+use bevy::asset::{AssetLoader, AsyncReadExt, LoadContext};
+use bevy::prelude::*;
+use bevy::reflect::TypePath;
+use bevy::utils::{BoxedFuture, HashMap};
+use rand::Rng;
+use rand_distr::{Distribution, Normal, Triangular};
+use serde::Deserialize;
+use std::fs;
+use std::path::Path;
+use thiserror::Error;
+
+/// Species definitions live in `assets/species/*.species.ron`, so crops can be
+/// added and tuned without recompiling. See `assets/species/apple.species.ron`
+/// for the expected layout.
+#[derive(Clone, Debug, Deserialize)]
+pub struct SpeciesDef {
+    pub name: String,
+    pub germination_needs: GerminationNeeds,
+    pub growing_needs: GrowingNeeds,
+    pub fruiting_needs: FruitingNeeds,
+    pub seeds_per_generation: u32,
+    /// should be roughly proportional to seeds_per_generation
+    pub spread_per_fruiting: u8,
+    pub spread_distance: u8,
+    pub food_value: u32,
+    pub wild_organisms_per_chunk: u16,
+    /// From 0 to 1, how well the species does in each biome; see `biome_preference`
+    #[serde(default)]
+    pub biome_preferences: HashMap<Biome, f32>,
+    pub min_generations: u32,
+    pub max_generations: u32,
+    #[serde(default)]
+    pub lifespan: LifespanDistribution,
+    pub decay_time: f32,
+    #[serde(default)]
+    pub pollination: Pollination,
+    pub blocks: PhaseBlocks,
+    /// Replaces the topsoil under a crop when it is harvested for the last time,
+    /// like the tilled soil wheat leaves behind
+    #[serde(default)]
+    pub harvested_ground: Option<BlockType>,
+    /// Species without one stay a single voxel
+    #[serde(default)]
+    pub tree: Option<TreeShape>,
+}
+
+/// How each organism's `Lifespan` is drawn; draws are clamped to
+/// `min_generations..=max_generations`
+#[derive(Clone, Copy, Debug, Default, PartialEq, Deserialize)]
+pub enum LifespanDistribution {
+    #[default]
+    Uniform,
+    /// Centred between the bounds, with `std_dev` in generations
+    Normal { std_dev: f32 },
+    /// Most likely to live `mode` generations
+    Triangular { mode: f32 },
+}
+
+/// How a `Mature` organism gets from flowering to `Pollinated`
+#[derive(Clone, Copy, Debug, Default, PartialEq, Deserialize)]
+pub enum Pollination {
+    /// Pollinates itself once its `MatureAgeTimer` runs out
+    #[default]
+    SelfPollinating,
+    /// Also needs another `Mature` organism of the same species within `radius` voxels
+    Cross { radius: u32 },
+}
+
+/// The block painted for an organism in each of its life phases. A `death` block
+/// of `Nothing` leaves nothing behind: the voxel goes straight back to what was there.
+#[derive(Clone, Debug, Deserialize)]
+pub struct PhaseBlocks {
+    pub seed: BlockType,
+    pub germinated: BlockType,
+    pub growing: BlockType,
+    pub mature: BlockType,
+    pub pollinated: BlockType,
+    pub fruiting: BlockType,
+    pub death: BlockType,
+}
+
+/// A tree grows a trunk up from its own voxel, with a canopy of leaves around the
+/// top, both growing over its `Growing` phase. Fruit hang under the canopy once it fruits.
+#[derive(Clone, Debug, PartialEq, Deserialize)]
+pub struct TreeShape {
+    /// Including the organism's own voxel
+    pub max_trunk_height: u8,
+    pub max_canopy_radius: u8,
+    pub fruit_count: u8,
+    pub trunk: BlockType,
+    pub canopy: BlockType,
+    pub fruit: BlockType,
+}
+
+impl SpeciesDef {
+    pub fn block_type(&self, phase: &LifePhase) -> BlockType {
+        match phase {
+            LifePhase::Seed => self.blocks.seed,
+            LifePhase::Germinated => self.blocks.germinated,
+            LifePhase::Growing { .. } => self.blocks.growing,
+            LifePhase::Mature => self.blocks.mature,
+            LifePhase::Pollinated { .. } => self.blocks.pollinated,
+            LifePhase::Fruiting => self.blocks.fruiting,
+            LifePhase::Death => self.blocks.death,
+        }
+    }
+
+    /// Scales how many wild organisms grow in the biome, and how fast seeds
+    /// germinate there. Biomes the species has no preference for count as 1.
+    pub fn biome_preference(&self, biome: Biome) -> f32 {
+        self.biome_preferences.get(&biome).copied().unwrap_or(1.)
+    }
+
+    /// Soil returned to the ground when the organism decays: half of what it needs to grow and fruit
+    pub fn decay_fertility(&self) -> u32 {
+        (self.growing_needs.soil.0 + self.fruiting_needs.soil.0) / 2
+    }
+
+    pub fn sample_lifespan(&self, rng: &mut impl Rng) -> Lifespan {
+        let (min, max) = (self.min_generations as f32, self.max_generations as f32);
+        let generations = match self.lifespan {
+            LifespanDistribution::Uniform => {
+                rng.gen_range(self.min_generations..=self.max_generations) as f32
+            }
+            LifespanDistribution::Normal { std_dev } => Normal::new((min + max) / 2., std_dev)
+                .map_or((min + max) / 2., |normal| normal.sample(rng)),
+            LifespanDistribution::Triangular { mode } => {
+                Triangular::new(min, max, mode).map_or(mode, |triangular| triangular.sample(rng))
+            }
+        };
+        Lifespan(generations.round().clamp(min, max) as u32)
+    }
+
+    /// Parses the contents of a species file and checks the species is valid
+    pub fn parse(bytes: &[u8]) -> Result<Self, SpeciesDefError> {
+        let def = ron::de::from_bytes::<SpeciesDef>(bytes)?;
+        def.validate()?;
+        Ok(def)
+    }
+
+    pub fn validate(&self) -> Result<(), SpeciesDefError> {
+        let invalid = |reason: &str| {
+            Err(SpeciesDefError::Invalid {
+                name: self.name.clone(),
+                reason: reason.to_string(),
+            })
+        };
+        let non_negative = |x: f32| x.is_finite() && x >= 0.;
+        if self.name.trim().is_empty() {
+            return invalid("name must not be empty");
+        }
+        if self.min_generations > self.max_generations {
+            return invalid("min_generations must not exceed max_generations");
+        }
+        match self.lifespan {
+            LifespanDistribution::Normal { std_dev } if !non_negative(std_dev) => {
+                return invalid("lifespan std_dev must be a non-negative number");
+            }
+            LifespanDistribution::Triangular { mode }
+                if !(self.min_generations as f32..=self.max_generations as f32).contains(&mode) =>
+            {
+                return invalid(
+                    "lifespan mode must be between min_generations and max_generations",
+                );
+            }
+            _ => {}
+        }
+        if self.spread_per_fruiting > 0 && self.spread_distance == 0 {
+            return invalid("spread_distance must be positive when spread_per_fruiting is");
+        }
+        if self.pollination == (Pollination::Cross { radius: 0 }) {
+            return invalid("cross pollination radius must be positive");
+        }
+        if matches!(self.harvested_ground, Some(ground) if !ground.is_topsoil()) {
+            return invalid("harvested_ground must be a topsoil block, so crops can be replanted");
+        }
+        if matches!(&self.tree, Some(tree) if tree.max_trunk_height == 0) {
+            return invalid("tree max_trunk_height must be positive");
+        }
+        if !self
+            .biome_preferences
+            .values()
+            .all(|preference| (0. ..=1.).contains(preference))
+        {
+            return invalid("biome preferences must be between 0 and 1");
+        }
+        if !non_negative(self.decay_time) {
+            return invalid("decay_time must be a non-negative number");
+        }
+        if ![
+            self.growing_needs.light,
+            self.growing_needs.time,
+            self.fruiting_needs.light,
+            self.fruiting_needs.time,
+        ]
+        .into_iter()
+        .all(non_negative)
+        {
+            return invalid("light and time needs must be non-negative numbers");
+        }
+        Ok(())
+    }
+}
+
+/// A species file as the `AssetServer` loaded it. Files that don't parse or
+/// aren't valid still load, with their error, so that one bad file doesn't hold up
+/// the rest; `build_species_registry` reports and skips them.
+#[derive(Asset, TypePath, Debug)]
+pub struct SpeciesFile(pub Result<SpeciesDef, SpeciesDefError>);
+
+#[derive(Debug, Error)]
+pub enum SpeciesDefError {
+    #[error("could not read species file: {0}")]
+    Io(#[from] std::io::Error),
+    #[error("could not parse species file: {0}")]
+    Ron(#[from] ron::error::SpannedError),
+    #[error("invalid species '{name}': {reason}")]
+    Invalid { name: String, reason: String },
+}
+
+#[derive(Default)]
+pub struct SpeciesDefLoader;
+
+impl AssetLoader for SpeciesDefLoader {
+    type Asset = SpeciesFile;
+    type Settings = ();
+    type Error = SpeciesDefError;
+
+    fn load<'a>(
+        &'a self,
+        reader: &'a mut Reader,
+        _settings: &'a (),
+        _load_context: &'a mut LoadContext,
+    ) -> BoxedFuture<'a, Result<SpeciesFile, SpeciesDefError>> {
+        Box::pin(async move {
+            let mut bytes = Vec::new();
+            reader.read_to_end(&mut bytes).await?;
+            Ok(SpeciesFile(SpeciesDef::parse(&bytes)))
+        })
+    }
+
+    fn extensions(&self) -> &[&str] {
+        &["species.ron"]
+    }
+}
+
+/// All known species, indexed by `Species` ids.
+#[derive(Resource, Default, Debug)]
+pub struct SpeciesRegistry {
+    defs: Vec<SpeciesDef>,
+    ids: HashMap<String, Species>,
+}
+
+impl SpeciesRegistry {
+    /// Species are sorted by name so ids do not depend on file load order.
+    /// Invalid species are logged and skipped.
+    pub fn from_defs(mut defs: Vec<SpeciesDef>) -> Self {
+        defs.sort_by(|a, b| a.name.cmp(&b.name));
+        let mut registry = SpeciesRegistry::default();
+        defs.into_iter().for_each(|def| {
+            if let Err(error) = registry.insert(def) {
+                error!("Skipping species: {error}");
+            }
+        });
+        registry
+    }
+
+    /// Reads every `*.species.ron` file in `dir` without going through the
+    /// `AssetServer`, for use when no asset pipeline is running.
+    #[allow(dead_code)]
+    pub fn from_dir(dir: &Path) -> Result<Self, SpeciesDefError> {
+        let mut defs = Vec::new();
+        for entry in fs::read_dir(dir)? {
+            let path = entry?.path();
+            if path.to_string_lossy().ends_with(".species.ron") {
+                defs.push(SpeciesDef::parse(&fs::read(path)?)?);
+            }
+        }
+        Ok(SpeciesRegistry::from_defs(defs))
+    }
+
+    pub fn insert(&mut self, def: SpeciesDef) -> Result<Species, SpeciesDefError> {
+        def.validate()?;
+        if self.ids.contains_key(&def.name) {
+            return Err(SpeciesDefError::Invalid {
+                name: def.name,
+                reason: "defined more than once".to_string(),
+            });
+        }
+        let species = Species(self.defs.len() as u16);
+        self.ids.insert(def.name.clone(), species);
+        self.defs.push(def);
+        Ok(species)
+    }
+
+    /// Species ids are only handed out by the registry, so this does not fail
+    pub fn get(&self, species: Species) -> &SpeciesDef {
+        &self.defs[species.0 as usize]
+    }
+
+    pub fn by_name(&self, name: &str) -> Option<Species> {
+        self.ids.get(name).copied()
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (Species, &SpeciesDef)> {
+        self.defs
+            .iter()
+            .enumerate()
+            .map(|(ix, def)| (Species(ix as u16), def))
+    }
+}
+
+#[cfg(test)]
+impl SpeciesRegistry {
+    /// The species in `assets/species`, built into the tests
+    pub fn for_tests() -> Self {
+        SpeciesRegistry::from_defs(
+            [
+                include_str!("../assets/species/apple.species.ron"),
+                include_str!("../assets/species/wheat.species.ron"),
+            ]
+            .into_iter()
+            .map(|file| SpeciesDef::parse(file.as_bytes()).unwrap())
+            .collect(),
+        )
+    }
+}
+
+/// Builds the `SpeciesRegistry` once the species files have been loaded, skipping
+/// the invalid ones
+pub fn build_species_registry(
+    mut commands: Commands,
+    species_assets: Res<SpeciesAssets>,
+    species_files: Res<Assets<SpeciesFile>>,
+) {
+    let registry = SpeciesRegistry::from_defs(
+        species_assets
+            .species
+            .iter()
+            .filter_map(|handle| match &species_files.get(handle)?.0 {
+                Ok(def) => Some(def.clone()),
+                Err(error) => {
+                    let path = handle
+                        .path()
+                        .map_or_else(|| "species file".to_string(), |path| path.to_string());
+                    error!("Skipping {path}: {error}");
+                    None
+                }
+            })
+            .collect(),
+    );
+    info!("loaded {} species", registry.defs.len());
+    commands.insert_resource(registry);
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::species::*;
+    use bevy::ecs::system::RunSystemOnce;
+    use rand::SeedableRng;
+    use rand_chacha::ChaCha8Rng;
+
+    const WHEAT: &str = include_str!("../assets/species/wheat.species.ron");
+
+    #[test]
+    fn shipped_species_parse() {
+        let def = ron::de::from_str::<SpeciesDef>(WHEAT).unwrap();
+        assert!(def.validate().is_ok());
+        assert_eq!(def.block_type(&LifePhase::Mature), BlockType::Wheat);
+    }
+
+    #[test]
+    fn invalid_species_files_are_skipped() {
+        let mut files = Assets::<SpeciesFile>::default();
+        let species = vec![
+            files.add(SpeciesFile(SpeciesDef::parse(WHEAT.as_bytes()))),
+            files.add(SpeciesFile(SpeciesDef::parse(
+                b"(name: \"Weed\", growing_needs: (",
+            ))),
+        ];
+        assert!(files.iter().any(|(_, file)| file.0.is_err()));
+        let mut world = World::new();
+        world.insert_resource(files);
+        world.insert_resource(SpeciesAssets { species });
+
+        world.run_system_once(build_species_registry);
+
+        let registry = world.resource::<SpeciesRegistry>();
+        assert!(registry.by_name("Wheat").is_some());
+        assert_eq!(registry.iter().count(), 1);
+    }
+
+    #[test]
+    fn duplicate_species_rejected() {
+        let mut registry = SpeciesRegistry::for_tests();
+        let wheat = registry.by_name("Wheat").unwrap();
+        assert!(registry.insert(registry.get(wheat).clone()).is_err());
+        assert_eq!(registry.by_name("Wheat"), Some(wheat));
+    }
+
+    #[test]
+    fn lifespans_stay_within_generation_bounds() {
+        let mut def = ron::de::from_str::<SpeciesDef>(WHEAT).unwrap();
+        def.min_generations = 10;
+        def.max_generations = 20;
+        let mut rng = ChaCha8Rng::seed_from_u64(0);
+        [
+            LifespanDistribution::Uniform,
+            LifespanDistribution::Normal { std_dev: 50. },
+            LifespanDistribution::Triangular { mode: 12. },
+        ]
+        .into_iter()
+        .for_each(|distribution| {
+            def.lifespan = distribution;
+            assert!(def.validate().is_ok());
+            assert!((0..100)
+                .map(|_| def.sample_lifespan(&mut rng).0)
+                .all(|generations| (10..=20).contains(&generations)));
+        });
+    }
+}
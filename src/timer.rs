@@ -1,10 +1,11 @@
 use crate::core_components::*;
 use crate::GameState;
 use bevy::prelude::*;
+use serde::{Deserialize, Serialize};
 
 pub struct TimerPlugin;
 
-#[derive(Clone, Debug, Component)]
+#[derive(Clone, Debug, Component, Serialize, Deserialize)]
 pub struct GameTimer {
     pub time: f32,
     pub is_active: bool,
@@ -0,0 +1,279 @@
+use crate::core_components::*;
+use crate::organism_index::OrganismIndex;
+use crate::species::{SpeciesRegistry, TreeShape};
+use crate::voxel_access::VoxelAccess;
+use crate::voxel_painting::{clear_voxel, paint_voxel_unchecked, SetVoxel};
+use bevy::prelude::*;
+use bevy_voxel_world::prelude::*;
+use itertools::Itertools;
+
+/// Trees, and anything else that can grow, tracked with `TreeVoxels`. Seeds and
+/// the dead don't grow.
+pub type TreeQuery<'w, 's> = Query<
+    'w,
+    's,
+    (
+        Entity,
+        &'static HasPosition,
+        &'static Species,
+        Option<&'static Growing>,
+        Option<Ref<'static, Fruiting>>,
+        Option<&'static mut TreeVoxels>,
+    ),
+    (Without<Seed>, Without<Germinated>, Without<Dead>),
+>;
+
+/// Trunk height and canopy radius for a tree that has come `progress` of the way
+/// through its `Growing` phase. The canopy only starts once there is a trunk to put it on.
+pub fn tree_size(shape: &TreeShape, progress: f32) -> (u8, u8) {
+    let grown = |max: u8| (progress * max as f32).round() as u8;
+    let trunk_height = 1 + grown(shape.max_trunk_height - 1);
+    let canopy_radius = if trunk_height > 1 {
+        grown(shape.max_canopy_radius)
+    } else {
+        0
+    };
+    (trunk_height, canopy_radius)
+}
+
+/// The voxels a tree at `base` takes up at the given size, leaving out `base` itself.
+/// The canopy is centred on the top of the trunk and listed from the centre outwards.
+pub fn tree_layout(base: IVec3, trunk_height: u8, canopy_radius: u8) -> (Vec<IVec3>, Vec<IVec3>) {
+    let trunk: Vec<IVec3> = (1..trunk_height as i32)
+        .map(|y| base + IVec3::Y * y)
+        .collect();
+    let top = base + IVec3::Y * (trunk_height as i32 - 1);
+    let radius = canopy_radius as i32;
+    let canopy = (-radius..=radius)
+        .flat_map(|x| {
+            (-radius..=radius)
+                .flat_map(move |y| (-radius..=radius).map(move |z| IVec3::new(x, y, z)))
+        })
+        .filter(|offset| canopy_radius > 0 && offset.length_squared() <= radius * radius)
+        .map(|offset| top + offset)
+        .filter(|pos| pos.y > base.y && !trunk.contains(pos))
+        .sorted_by_key(|pos| ((*pos - top).length_squared(), pos.y, pos.x, pos.z))
+        .collect();
+    (trunk, canopy)
+}
+
+/// Air that no organism has claimed, or one of the tree's own leaves
+fn is_free(
+    voxel_world: &impl VoxelAccess,
+    index: &OrganismIndex,
+    tree: &TreeVoxels,
+    pos: IVec3,
+) -> bool {
+    tree.canopy.contains(&pos)
+        || (voxel_world.get_voxel(pos) == WorldVoxel::Air && !index.is_occupied(pos))
+}
+
+fn clear_voxels(voxel_edits: &mut EventWriter<SetVoxel>, voxels: impl IntoIterator<Item = IVec3>) {
+    voxels
+        .into_iter()
+        .for_each(|pos| clear_voxel(voxel_edits, pos));
+}
+
+/// Grows `tree` towards the size its `progress` calls for. The trunk stops at the
+/// first voxel that isn't free and leaves only grow into free voxels, trying again
+/// each time the tree grows. Leaves move up as the trunk does.
+#[allow(clippy::too_many_arguments)]
+pub fn grow_tree(
+    voxel_world: &impl VoxelAccess,
+    index: &OrganismIndex,
+    voxel_edits: &mut EventWriter<SetVoxel>,
+    base: IVec3,
+    shape: &TreeShape,
+    progress: f32,
+    tree: &mut TreeVoxels,
+) {
+    let size = tree_size(shape, progress);
+    if size == tree.size {
+        return;
+    }
+    tree.size = size;
+
+    let (trunk, _) = tree_layout(base, size.0, 0);
+    for pos in trunk.into_iter().skip(tree.trunk.len()) {
+        if !is_free(voxel_world, index, tree, pos) {
+            break;
+        }
+        tree.canopy.retain(|leaf| *leaf != pos);
+        tree.trunk.push(pos);
+        paint_voxel_unchecked(voxel_edits, pos, shape.trunk);
+    }
+
+    let (_, canopy) = tree_layout(base, tree.trunk.len() as u8 + 1, size.1);
+    let (kept, dropped): (Vec<IVec3>, Vec<IVec3>) = tree
+        .canopy
+        .drain(..)
+        .partition(|leaf| canopy.contains(leaf));
+    tree.canopy = kept;
+    clear_voxels(voxel_edits, dropped);
+    canopy.into_iter().for_each(|pos| {
+        if !tree.contains(pos) && is_free(voxel_world, index, tree, pos) {
+            tree.canopy.push(pos);
+            paint_voxel_unchecked(voxel_edits, pos, shape.canopy);
+        }
+    });
+}
+
+/// Replaces any fruit left from the last time the tree fruited with up to
+/// `fruit_count` new ones, hanging in free voxels under the canopy
+pub fn bear_fruit(
+    voxel_world: &impl VoxelAccess,
+    index: &OrganismIndex,
+    voxel_edits: &mut EventWriter<SetVoxel>,
+    shape: &TreeShape,
+    tree: &mut TreeVoxels,
+) {
+    let old_fruit: Vec<IVec3> = tree.fruit.drain(..).collect();
+    clear_voxels(voxel_edits, old_fruit.iter().copied());
+    tree.fruit = tree
+        .canopy
+        .iter()
+        .map(|leaf| *leaf - IVec3::Y)
+        .unique()
+        .filter(|pos| {
+            !tree.contains(*pos)
+                && !old_fruit.contains(pos)
+                && is_free(voxel_world, index, tree, *pos)
+        })
+        .take(shape.fruit_count as usize)
+        .collect();
+    tree.fruit
+        .iter()
+        .for_each(|pos| paint_voxel_unchecked(voxel_edits, *pos, shape.fruit));
+}
+
+/// Removes every voxel the tree has grown
+pub fn clear_tree(voxel_edits: &mut EventWriter<SetVoxel>, tree: &TreeVoxels) {
+    clear_voxels(voxel_edits, tree.iter().copied());
+}
+
+/// Grows each tree's trunk and canopy, and its fruit when it starts `Fruiting`.
+/// Organisms get their `TreeVoxels` here, the first time their species' tree grows.
+pub fn grow_trees(
+    commands: &mut Commands,
+    voxel_world: &impl VoxelAccess,
+    index: &OrganismIndex,
+    species_registry: &SpeciesRegistry,
+    voxel_edits: &mut EventWriter<SetVoxel>,
+    trees: &mut TreeQuery,
+) {
+    trees.for_each_mut(
+        |(entity, HasPosition { pos }, species, growing, fruiting, tree_voxels)| {
+            let species_def = species_registry.get(*species);
+            let Some(shape) = &species_def.tree else {
+                return;
+            };
+            let progress = growing.map_or(1., |growing| {
+                growing.needs.progress(&species_def.growing_needs)
+            });
+            let starts_fruiting = fruiting.is_some_and(|fruiting| fruiting.is_added());
+            let mut grow_and_fruit = |tree: &mut TreeVoxels| {
+                grow_tree(voxel_world, index, voxel_edits, *pos, shape, progress, tree);
+                if starts_fruiting {
+                    bear_fruit(voxel_world, index, voxel_edits, shape, tree);
+                }
+            };
+            match tree_voxels {
+                Some(mut tree) => grow_and_fruit(&mut *tree),
+                None => {
+                    let mut tree = TreeVoxels::default();
+                    grow_and_fruit(&mut tree);
+                    commands.entity(entity).insert(tree);
+                }
+            }
+        },
+    )
+}
+
+pub fn grow_trees_system(
+    mut commands: Commands,
+    voxel_world: VoxelWorld,
+    index: Res<OrganismIndex>,
+    species_registry: Res<SpeciesRegistry>,
+    mut voxel_edits: EventWriter<SetVoxel>,
+    mut trees: TreeQuery,
+) {
+    grow_trees(
+        &mut commands,
+        &voxel_world,
+        &index,
+        &species_registry,
+        &mut voxel_edits,
+        &mut trees,
+    );
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::block_types::BlockType;
+    use crate::trees::*;
+    use crate::voxel_access::VoxelGrid;
+    use bevy::ecs::event::Events;
+    use bevy::ecs::system::RunSystemOnce;
+
+    fn apple_tree() -> TreeShape {
+        TreeShape {
+            max_trunk_height: 5,
+            max_canopy_radius: 2,
+            fruit_count: 4,
+            trunk: BlockType::TreeTrunk,
+            canopy: BlockType::Leaves,
+            fruit: BlockType::Apple,
+        }
+    }
+
+    fn grow(world: &mut World, voxels: VoxelGrid, progress: f32, tree: TreeVoxels) -> TreeVoxels {
+        world.insert_resource(TestTree(tree));
+        world.run_system_once(
+            move |mut voxel_edits: EventWriter<SetVoxel>, mut tree: ResMut<TestTree>| {
+                grow_tree(
+                    &voxels,
+                    &OrganismIndex::default(),
+                    &mut voxel_edits,
+                    IVec3::ZERO,
+                    &apple_tree(),
+                    progress,
+                    &mut tree.0,
+                );
+            },
+        );
+        world.remove_resource::<TestTree>().unwrap().0
+    }
+
+    #[derive(Resource)]
+    struct TestTree(TreeVoxels);
+
+    #[test]
+    fn trees_grow_a_trunk_and_canopy() {
+        let mut world = World::new();
+        world.init_resource::<Events<SetVoxel>>();
+        let sapling = grow(&mut world, VoxelGrid::default(), 0.1, TreeVoxels::default());
+        assert!(sapling.trunk.is_empty() && sapling.canopy.is_empty());
+
+        let grown = grow(&mut world, VoxelGrid::default(), 1., sapling);
+        assert_eq!(
+            grown.trunk,
+            (1..5).map(|y| IVec3::Y * y).collect::<Vec<_>>()
+        );
+        assert!(grown.canopy.contains(&IVec3::new(0, 6, 0)));
+        assert!(grown.canopy.iter().all(|leaf| !grown.trunk.contains(leaf)));
+    }
+
+    #[test]
+    fn trunks_stop_below_obstacles() {
+        let mut world = World::new();
+        world.init_resource::<Events<SetVoxel>>();
+        let mut voxels = VoxelGrid::default();
+        voxels.set_voxel(IVec3::Y * 3, WorldVoxel::Solid(BlockType::Dirt.index()));
+        let tree = grow(&mut world, voxels, 1., TreeVoxels::default());
+
+        assert_eq!(tree.trunk, vec![IVec3::Y, IVec3::Y * 2]);
+        assert!(!tree.canopy.contains(&(IVec3::Y * 3)));
+        let painted = world.resource::<Events<SetVoxel>>().len();
+        assert_eq!(painted, tree.trunk.len() + tree.canopy.len());
+    }
+}
@@ -3,15 +3,20 @@ use bevy::prelude::*;
 
 use crate::player::Player;
 use crate::timer::GameTimer;
+use crate::weather::Weather;
 use crate::{core_components::PlayerInventory, loading::TextureAssets, GameState};
 
 pub struct UiHandlerPlugin;
 
+/// How long a `HudMessage` stays on screen, in seconds
+const MESSAGE_SECONDS: f32 = 5.0;
+
 /// This plugin handles the ui related stuff like the displaying of entitys and the crosshair
 /// is only active during the State `GameState::Playing`
 impl Plugin for UiHandlerPlugin {
     fn build(&self, app: &mut App) {
-        app.insert_resource(HudData::default());
+        app.insert_resource(HudData::default())
+            .add_event::<HudMessage>();
         app.add_systems(Update, render_ui.run_if(in_state(GameState::Playing)));
     }
 }
@@ -22,8 +27,14 @@ struct HudData {
     entities: u32,
     resource_count: u32,
     score: u32,
+    /// The latest `HudMessage`, and how much longer it is shown for
+    message: Option<(String, f32)>,
 }
 
+/// Shows the player a line of text for a few seconds, e.g. why a save couldn't be loaded
+#[derive(Event, Clone, Debug)]
+pub struct HudMessage(pub String);
+
 #[derive(Component)]
 pub struct Hud {}
 
@@ -33,11 +44,13 @@ fn render_ui(
     entities: &Entities,
     textures: Res<TextureAssets>,
     time: Res<Time>,
+    weather: Res<Weather>,
     mut hud_data: ResMut<HudData>,
     windows: Query<&Window>,
     hud_query: Query<Entity, With<Hud>>,
     inventory_query: Query<&PlayerInventory, With<Player>>,
     timer_query: Query<&GameTimer>,
+    mut messages: EventReader<HudMessage>,
 ) {
     let timer = timer_query.single();
     let window: &Window = windows.single();
@@ -56,6 +69,17 @@ fn render_ui(
             .sum();
     }
 
+    if let Some(HudMessage(message)) = messages.read().last() {
+        hud_data.message = Some((message.clone(), MESSAGE_SECONDS));
+    }
+    if let Some((_, remaining)) = &mut hud_data.message {
+        *remaining -= time.delta_seconds();
+    }
+    hud_data.message = hud_data
+        .message
+        .take()
+        .filter(|(_, remaining)| *remaining > 0.0);
+
     hud_query.for_each(|hud| commands.entity(hud).despawn_recursive());
     // render the score, resources and the entities
     commands
@@ -106,6 +130,24 @@ fn render_ui(
                     ..default()
                 },
             ));
+            children.spawn(TextBundle::from_section(
+                format!("Weather: {}", weather.kind),
+                TextStyle {
+                    font_size: 20.0,
+                    color: Color::rgb(0.9, 0.9, 0.9),
+                    ..default()
+                },
+            ));
+            if let Some((message, _)) = &hud_data.message {
+                children.spawn(TextBundle::from_section(
+                    message.clone(),
+                    TextStyle {
+                        font_size: 20.0,
+                        color: Color::rgb(0.9, 0.6, 0.3),
+                        ..default()
+                    },
+                ));
+            }
         });
 
     // draw a crosshair onto the screen
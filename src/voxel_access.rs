@@ -0,0 +1,276 @@
+use bevy::prelude::*;
+use bevy::utils::HashMap;
+use bevy_voxel_world::prelude::*;
+use rand::Rng;
+use std::collections::BTreeSet;
+
+/// Read and write access to voxels, so game logic can run against either the
+/// rendered `VoxelWorld` or a plain in-memory `VoxelGrid`.
+pub trait VoxelAccess {
+    fn get_voxel(&self, pos: IVec3) -> WorldVoxel;
+
+    /// `WorldVoxel::Unset` should read back as the voxel the world was generated with
+    fn set_voxel(&mut self, pos: IVec3, voxel: WorldVoxel);
+
+    /// The topmost solid voxel in the column at `pos`
+    fn get_surface_voxel_at_2d_pos(&self, pos: Vec2) -> Option<(IVec3, WorldVoxel)>;
+
+    /// The surface voxel of a random column within `radius` of `position`
+    fn get_random_surface_voxel(
+        &self,
+        position: IVec3,
+        radius: u32,
+        rng: &mut impl Rng,
+    ) -> Option<(IVec3, WorldVoxel)> {
+        let radius = radius as i32;
+        let x = position.x + rng.gen_range(-radius..=radius);
+        let z = position.z + rng.gen_range(-radius..=radius);
+        self.get_surface_voxel_at_2d_pos(Vec2::new(x as f32, z as f32))
+    }
+}
+
+impl VoxelAccess for VoxelWorld<'_> {
+    fn get_voxel(&self, pos: IVec3) -> WorldVoxel {
+        VoxelWorld::get_voxel(self, pos)
+    }
+
+    fn set_voxel(&mut self, pos: IVec3, voxel: WorldVoxel) {
+        VoxelWorld::set_voxel(self, pos, voxel)
+    }
+
+    fn get_surface_voxel_at_2d_pos(&self, pos: Vec2) -> Option<(IVec3, WorldVoxel)> {
+        VoxelWorld::get_surface_voxel_at_2d_pos(self, pos)
+    }
+}
+
+/// Voxels kept in memory; positions not in the grid are air, which is what the
+/// grid's world is generated with. The solid voxels are also indexed by column,
+/// so finding the surface doesn't mean looking through the whole grid.
+#[derive(Clone, Default, Debug)]
+pub struct VoxelGrid {
+    voxels: HashMap<IVec3, WorldVoxel>,
+    /// The heights of the solid voxels in each column
+    solid_heights: HashMap<IVec2, BTreeSet<i32>>,
+}
+
+impl VoxelGrid {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn get(&self, pos: &IVec3) -> Option<&WorldVoxel> {
+        self.voxels.get(pos)
+    }
+
+    pub fn insert(&mut self, pos: IVec3, voxel: WorldVoxel) -> Option<WorldVoxel> {
+        if matches!(voxel, WorldVoxel::Solid(_)) {
+            self.solid_heights
+                .entry(pos.xz())
+                .or_default()
+                .insert(pos.y);
+        } else {
+            self.forget_solid(pos);
+        }
+        self.voxels.insert(pos, voxel)
+    }
+
+    pub fn remove(&mut self, pos: &IVec3) -> Option<WorldVoxel> {
+        self.forget_solid(*pos);
+        self.voxels.remove(pos)
+    }
+
+    fn forget_solid(&mut self, pos: IVec3) {
+        if let Some(heights) = self.solid_heights.get_mut(&pos.xz()) {
+            heights.remove(&pos.y);
+            if heights.is_empty() {
+                self.solid_heights.remove(&pos.xz());
+            }
+        }
+    }
+
+    pub fn keys(&self) -> impl Iterator<Item = &IVec3> {
+        self.voxels.keys()
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (&IVec3, &WorldVoxel)> {
+        self.voxels.iter()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.voxels.is_empty()
+    }
+}
+
+impl FromIterator<(IVec3, WorldVoxel)> for VoxelGrid {
+    fn from_iter<T: IntoIterator<Item = (IVec3, WorldVoxel)>>(iter: T) -> Self {
+        let mut grid = VoxelGrid::new();
+        iter.into_iter().for_each(|(pos, voxel)| {
+            grid.insert(pos, voxel);
+        });
+        grid
+    }
+}
+
+impl IntoIterator for VoxelGrid {
+    type Item = (IVec3, WorldVoxel);
+    type IntoIter = <HashMap<IVec3, WorldVoxel> as IntoIterator>::IntoIter;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.voxels.into_iter()
+    }
+}
+
+impl VoxelAccess for VoxelGrid {
+    fn get_voxel(&self, pos: IVec3) -> WorldVoxel {
+        self.get(&pos).copied().unwrap_or(WorldVoxel::Air)
+    }
+
+    fn set_voxel(&mut self, pos: IVec3, voxel: WorldVoxel) {
+        if voxel == WorldVoxel::Unset {
+            self.remove(&pos);
+        } else {
+            self.insert(pos, voxel);
+        }
+    }
+
+    fn get_surface_voxel_at_2d_pos(&self, pos: Vec2) -> Option<(IVec3, WorldVoxel)> {
+        let column = pos.floor().as_ivec2();
+        let y = *self.solid_heights.get(&column)?.last()?;
+        let pos = IVec3::new(column.x, y, column.y);
+        Some((pos, self.get_voxel(pos)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::block_types::BlockType;
+    use crate::core_components::*;
+    use crate::lifecycles::{decay_system, LifeCycleBudget};
+    use crate::organism_index::OrganismIndex;
+    use crate::sim_rng::{RngStream, WorldSeed};
+    use crate::soil::{SoilFertility, INITIAL_FERTILITY};
+    use crate::species::SpeciesRegistry;
+    use crate::voxel_access::*;
+    use crate::voxel_painting::*;
+    use bevy::ecs::event::Events;
+    use bevy::ecs::system::{CommandQueue, RunSystemOnce};
+
+    fn dirt() -> WorldVoxel {
+        WorldVoxel::Solid(BlockType::Dirt.index())
+    }
+
+    fn dirt_floor(half_width: i32) -> VoxelGrid {
+        let mut grid = VoxelGrid::new();
+        (-half_width..=half_width).for_each(|x| {
+            (-half_width..=half_width).for_each(|z| grid.set_voxel(IVec3::new(x, 0, z), dirt()))
+        });
+        grid
+    }
+
+    #[test]
+    fn plants_only_on_soil_with_air_above() {
+        let mut grid = dirt_floor(1);
+        grid.set_voxel(
+            IVec3::new(1, 0, 0),
+            WorldVoxel::Solid(BlockType::Water.index()),
+        );
+        assert_eq!(get_growth_voxel(&grid, dirt(), IVec3::ZERO), Some(IVec3::Y));
+        assert_eq!(
+            get_growth_voxel(&grid, grid.get_voxel(IVec3::X), IVec3::X),
+            None
+        );
+        grid.set_voxel(IVec3::Y, dirt());
+        assert_eq!(get_growth_voxel(&grid, dirt(), IVec3::ZERO), None);
+        let grass = WorldVoxel::Solid(BlockType::Grass.index());
+        let sand = WorldVoxel::Solid(BlockType::Sand.index());
+        assert_eq!(get_growth_voxel(&grid, grass, IVec3::Y), Some(IVec3::Y * 2));
+        assert_eq!(get_growth_voxel(&grid, sand, IVec3::Y), None);
+        let stone = WorldVoxel::Solid(BlockType::Stone.index());
+        assert_eq!(get_growth_voxel(&grid, stone, IVec3::Y), None);
+    }
+
+    #[test]
+    fn the_surface_is_the_topmost_solid_voxel() {
+        let mut grid = dirt_floor(0);
+        grid.set_voxel(IVec3::Y * 3, dirt());
+        grid.set_voxel(IVec3::Y * 4, WorldVoxel::Air);
+        assert_eq!(
+            grid.get_surface_voxel_at_2d_pos(Vec2::new(0.5, 0.5)),
+            Some((IVec3::Y * 3, dirt()))
+        );
+        grid.set_voxel(IVec3::Y * 3, WorldVoxel::Air);
+        assert_eq!(
+            grid.get_surface_voxel_at_2d_pos(Vec2::ZERO),
+            Some((IVec3::ZERO, dirt()))
+        );
+        grid.set_voxel(IVec3::ZERO, WorldVoxel::Unset);
+        assert_eq!(grid.get_surface_voxel_at_2d_pos(Vec2::ZERO), None);
+    }
+
+    #[test]
+    fn spreading_seeds_lands_on_the_surface() {
+        let grid = dirt_floor(2);
+        let mut world = World::new();
+        let mut index = OrganismIndex::default();
+        let spread = SpreadSeeds {
+            species: Species(0),
+            origin: IVec3::Y,
+            distance: 2,
+            count: 3,
+            owner: None,
+        };
+        // The same draws twice, before either spawn has been applied
+        let mut queue = CommandQueue::default();
+        (0..2).for_each(|_| {
+            spread_seeds(
+                &mut Commands::new(&mut queue, &world),
+                &mut index,
+                &grid,
+                &spread,
+                &mut WorldSeed(0).rng(RngStream::SeedSpreading),
+            )
+        });
+        queue.apply(&mut world);
+
+        let positions: Vec<IVec3> = world
+            .query::<&HasPosition>()
+            .iter(&world)
+            .map(|HasPosition { pos }| *pos)
+            .collect();
+        assert_eq!(positions.len(), 3);
+        assert_eq!(index.len(), 3);
+        assert!(positions.iter().all(|pos| pos.y == 1));
+    }
+
+    #[test]
+    fn decayed_organisms_clear_their_voxel() {
+        let mut grid = dirt_floor(0);
+        grid.set_voxel(IVec3::Y, WorldVoxel::Solid(BlockType::DeadTree.index()));
+        let registry = SpeciesRegistry::for_tests();
+        let apple = registry.by_name("Apple").unwrap();
+        let mut world = World::new();
+        world.insert_resource(registry);
+        world.init_resource::<SoilFertility>();
+        world.init_resource::<Time>();
+        world.init_resource::<Events<SetVoxel>>();
+        world.init_resource::<LifeCycleBudget>();
+        let organism = world
+            .spawn((
+                apple,
+                HasPosition { pos: IVec3::Y },
+                Dead,
+                DecayTimer(0.),
+                LastProcessed::default(),
+            ))
+            .id();
+
+        world.run_system_once(decay_system);
+        let voxel_edits: Vec<SetVoxel> = world.resource_mut::<Events<SetVoxel>>().drain().collect();
+        apply_voxel_edits(&mut grid, voxel_edits);
+
+        assert!(world.get_entity(organism).is_none());
+        assert_eq!(grid.get_voxel(IVec3::Y), WorldVoxel::Air);
+        assert!(grid.get(&IVec3::Y).is_none());
+        assert!(world.resource::<SoilFertility>().get(IVec2::ZERO) > INITIAL_FERTILITY);
+    }
+}
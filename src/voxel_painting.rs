@@ -1,12 +1,44 @@
-use crate::{block_types::BlockType, core_components::*, spawner::spawn_organism};
+use crate::{
+    block_types::BlockType,
+    core_components::*,
+    map_setup::TerrainGenerator,
+    organism_index::OrganismIndex,
+    sim_rng::{RngStream, SimRng},
+    spawner::spawn_organism,
+    species::SpeciesRegistry,
+    voxel_access::VoxelAccess,
+};
 use bevy::prelude::*;
 use bevy_voxel_world::prelude::*;
+use itertools::Itertools;
+use rand::Rng;
+use std::iter;
 
 const STEP_SIZE: f32 = 0.99;
 const MAX_SEARCH_DISTANCE: f32 = 518.; // ceil(512/STEP_SIZE)
+/// Attempts at finding a surface voxel per seed, before giving up on it
+const SURFACE_SEARCH_TRIES: usize = 8;
+
+/// A request to change a single voxel, applied by whichever voxel backend is running.
+/// `WorldVoxel::Unset` removes the voxel's edit, putting back what the world was generated with.
+#[derive(Event, Clone, Debug)]
+pub struct SetVoxel {
+    pub pos: IVec3,
+    pub voxel: WorldVoxel,
+}
+
+/// A fruiting organism scattering seeds onto nearby surface voxels
+#[derive(Event, Clone, Debug)]
+pub struct SpreadSeeds {
+    pub species: Species,
+    pub origin: IVec3,
+    pub distance: u8,
+    pub count: u8,
+    pub owner: Option<Entity>,
+}
 
 pub fn ray_cast_to_voxel(
-    voxel_world: &VoxelWorld,
+    voxel_world: &impl VoxelAccess,
     player_position: Vec3,
     look_direction: Vec3,
 ) -> Option<(IVec3, WorldVoxel)> {
@@ -20,16 +52,18 @@ pub fn ray_cast_to_voxel(
         .find(|&(_, voxel)| matches!(voxel, WorldVoxel::Solid(_)))
 }
 
+pub fn is_topsoil_voxel(voxel: WorldVoxel) -> bool {
+    matches!(voxel, WorldVoxel::Solid(block) if BlockType::from_repr(block).is_some_and(BlockType::is_topsoil))
+}
+
 pub fn get_growth_voxel(
-    voxel_world: &VoxelWorld,
+    voxel_world: &impl VoxelAccess,
     voxel: WorldVoxel,
     vox_pos: IVec3,
 ) -> Option<IVec3> {
     let above_pos = vox_pos + IVec3::Y;
     let above_vox = voxel_world.get_voxel(above_pos);
-    let vox_pair = (voxel, above_vox);
-    // TODO: in the future we should allow multiple types of surface voxes to grow plants
-    if vox_pair == (WorldVoxel::Solid(BlockType::Dirt.index()), WorldVoxel::Air) {
+    if is_topsoil_voxel(voxel) && above_vox == WorldVoxel::Air {
         Some(above_pos)
     } else {
         None
@@ -37,7 +71,7 @@ pub fn get_growth_voxel(
 }
 
 pub fn get_surface_air_voxel(
-    voxel_world: &VoxelWorld,
+    voxel_world: &impl VoxelAccess,
     player_position: Vec3,
     look_direction: Vec3,
 ) -> Option<IVec3> {
@@ -50,30 +84,145 @@ pub fn get_surface_air_voxel(
 }
 
 pub fn paint_voxel_system(
+    species_registry: Res<SpeciesRegistry>,
     mut commands: Commands,
-    mut voxel_world: VoxelWorld,
+    mut index: ResMut<OrganismIndex>,
+    mut voxel_edits: EventWriter<SetVoxel>,
     paint_query: Query<(Entity, &PlayerWantsToPaintVoxel)>,
 ) {
     paint_query.for_each(|(paint_entity, want_to_paint)| {
         // TODO: maybe add more checks to see if it is OK to paint,
         // but probably don't want to be redundant with get_surface_air_voxel
-        let PaintableResources::SeedCrop(species) = want_to_paint.paint_as.clone();
-        spawn_organism(
+        commands.entity(paint_entity).despawn();
+        // The voxel isn't painted until later, so a second request for the
+        // same voxel can't be caught by get_surface_air_voxel
+        if index.is_occupied(want_to_paint.pos) {
+            return;
+        }
+        let PaintableResources::SeedCrop(species) = want_to_paint.paint_as;
+        let organism = spawn_organism(
             &mut commands,
-            species.clone(),
+            species,
             crate::core_components::LifePhase::Seed,
             want_to_paint.pos,
             Some(want_to_paint.player),
         );
+        index.insert(organism, want_to_paint.pos);
 
-        let block_type = species.block_type(&SEED_PHASE);
-        paint_voxel_unchecked(&mut voxel_world, want_to_paint.pos, block_type);
-        commands.entity(paint_entity).despawn();
+        let block_type = species_registry.get(species).block_type(&SEED_PHASE);
+        paint_voxel_unchecked(&mut voxel_edits, want_to_paint.pos, block_type);
     })
 }
 
 /// It is assumed that the type of voxel to be painted is correct according
 /// to the game logic, as `paint_voxel_unchecked` does no checking
-pub fn paint_voxel_unchecked(voxel_world: &mut VoxelWorld, pos: IVec3, block_type: BlockType) {
-    voxel_world.set_voxel(pos, WorldVoxel::Solid(block_type.index()));
+pub fn paint_voxel_unchecked(
+    voxel_edits: &mut EventWriter<SetVoxel>,
+    pos: IVec3,
+    block_type: BlockType,
+) {
+    voxel_edits.send(SetVoxel {
+        pos,
+        voxel: WorldVoxel::Solid(block_type.index()),
+    });
+}
+
+/// Puts back the voxel the world was generated with, for when an organism is gone
+pub fn clear_voxel(voxel_edits: &mut EventWriter<SetVoxel>, pos: IVec3) {
+    voxel_edits.send(SetVoxel {
+        pos,
+        voxel: WorldVoxel::Unset,
+    });
+}
+
+pub fn apply_voxel_edits(
+    voxel_world: &mut impl VoxelAccess,
+    voxel_edits: impl IntoIterator<Item = SetVoxel>,
+) {
+    voxel_edits
+        .into_iter()
+        .for_each(|edit| voxel_world.set_voxel(edit.pos, edit.voxel));
+}
+
+/// The `VoxelWorld` would read `WorldVoxel::Unset` back as `Unset` rather than as
+/// the generated voxel, so it is given the generated voxel instead
+pub fn apply_voxel_edits_system(
+    mut voxel_world: VoxelWorld,
+    terrain: Res<TerrainGenerator>,
+    mut voxel_edits: EventReader<SetVoxel>,
+) {
+    apply_voxel_edits(
+        &mut voxel_world,
+        voxel_edits.read().map(|edit| SetVoxel {
+            pos: edit.pos,
+            voxel: terrain.or_generated(edit.pos, edit.voxel),
+        }),
+    );
+}
+
+pub fn spread_seeds(
+    commands: &mut Commands,
+    index: &mut OrganismIndex,
+    voxel_world: &impl VoxelAccess,
+    spread: &SpreadSeeds,
+    rng: &mut impl Rng,
+) {
+    get_random_surface_voxels(
+        voxel_world,
+        spread.origin,
+        spread.distance,
+        spread.count,
+        rng,
+    )
+    .into_iter()
+    .filter_map(|(pos, voxel)| get_growth_voxel(voxel_world, voxel, pos))
+    .filter(|growth_pos| !index.is_occupied(*growth_pos))
+    .for_each(|growth_pos| {
+        let organism = spawn_organism(
+            commands,
+            spread.species,
+            LifePhase::Seed,
+            growth_pos,
+            spread.owner,
+        );
+        index.insert(organism, growth_pos);
+    })
+}
+
+pub fn spread_seeds_system(
+    mut commands: Commands,
+    mut index: ResMut<OrganismIndex>,
+    voxel_world: VoxelWorld,
+    mut sim_rng: ResMut<SimRng>,
+    mut seed_spreads: EventReader<SpreadSeeds>,
+) {
+    let rng = sim_rng.stream(RngStream::SeedSpreading);
+    seed_spreads
+        .read()
+        .for_each(|spread| spread_seeds(&mut commands, &mut index, &voxel_world, spread, rng));
+}
+
+/// Gives up early, with fewer than `n` voxels, if the surface around
+/// `position` is too sparse. Voxels are distinct, in the order they were drawn.
+pub fn get_random_surface_voxels(
+    voxel_world: &impl VoxelAccess,
+    position: IVec3,
+    radius: u8,
+    n: u8,
+    rng: &mut impl Rng,
+) -> Vec<(IVec3, WorldVoxel)> {
+    iter::repeat_with(|| voxel_world.get_random_surface_voxel(position, radius.into(), rng))
+        .take(n as usize * SURFACE_SEARCH_TRIES)
+        .flatten()
+        .fold_while(Vec::new(), |mut voxels, voxel| {
+            if !voxels.contains(&voxel) {
+                voxels.push(voxel);
+            }
+            if voxels.len() < n as usize {
+                itertools::FoldWhile::Continue(voxels)
+            } else {
+                itertools::FoldWhile::Done(voxels)
+            }
+        })
+        .into_inner()
 }
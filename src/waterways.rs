@@ -0,0 +1,401 @@
+use crate::shared_cache::SharedCache;
+use bevy::math::{DVec2, IVec2};
+use std::sync::Arc;
+
+/// Rivers and lakes are laid out per cell of this many columns square
+const CELL_SIZE: i32 = 256;
+/// Chances out of 100 of a river rising in a cell, and of a lake being looked for in it
+const RIVER_CHANCE: u64 = 50;
+const LAKE_CHANCE: u64 = 30;
+/// Points in the cell a river's source is picked from, the highest of them
+const SOURCE_CANDIDATES: u64 = 4;
+/// Rivers rise at least this far above the sea
+const MIN_SOURCE_HEIGHT: f64 = 8.0;
+/// A river's course is followed downhill in steps of this many columns
+const RIVER_STEP: i32 = 8;
+/// Rivers that haven't reached the sea after this many steps aren't carved at all
+const MAX_RIVER_STEPS: usize = 96;
+const RIVER_HALF_WIDTH: f64 = 4.0;
+/// Depth of the water in the middle of a river
+const RIVER_DEPTH: f64 = 3.0;
+/// The bottom of a hollow is found by running downhill in steps of this many columns
+const LAKE_STEP: i32 = 4;
+const MAX_LAKE_STEPS: usize = 32;
+/// How far from the bottom of its hollow a lake's shore is looked for
+const LAKE_RADIUS: f64 = 24.0;
+/// Points around the shore a lake's level is found from
+const LAKE_SHORE_SAMPLES: usize = 16;
+/// Cells this far around a column may have a river or lake reaching into it
+const CELL_REACH: i32 = MAX_RIVER_STEPS as i32 * RIVER_STEP / CELL_SIZE + 1;
+
+/// Carves rivers and lakes into the terrain. Each cell's rivers and lakes are
+/// worked out from the height map alone, without looking at any chunk's voxels,
+/// so chunks can be generated in any order and always come out the same. They are
+/// worked out once and shared by every chunk they reach into, as the cache is
+/// shared by every clone.
+#[derive(Clone)]
+pub struct Waterways {
+    seed: u64,
+    cells: SharedCache<CellWaters>,
+}
+
+/// The rivers and lakes that could reach the columns of an area, see `Waterways::around`
+pub struct NearbyWaters(Vec<Arc<CellWaters>>);
+
+/// The river rising in a cell, and the lake in the hollow its cell drains into
+#[derive(Debug, Default)]
+struct CellWaters {
+    river: Option<River>,
+    lake: Option<Lake>,
+}
+
+/// A river running downhill from its source until it reaches the sea
+#[derive(Debug)]
+struct River {
+    /// The points the river's course runs through, with its level at each. The
+    /// level drops at every point until it is down to sea level.
+    course: Vec<(DVec2, i32)>,
+    min: DVec2,
+    max: DVec2,
+}
+
+/// A lake filling a hollow up to `level`, just below the lowest point of its shore
+#[derive(Clone, Copy, Debug, PartialEq)]
+struct Lake {
+    bottom: DVec2,
+    level: i32,
+}
+
+impl River {
+    fn new(course: Vec<(DVec2, i32)>) -> Self {
+        let (min, max) = course.iter().fold(
+            (DVec2::splat(f64::INFINITY), DVec2::splat(f64::NEG_INFINITY)),
+            |(min, max), (point, _)| (min.min(*point), max.max(*point)),
+        );
+        River {
+            course,
+            min: min - DVec2::splat(RIVER_HALF_WIDTH),
+            max: max + DVec2::splat(RIVER_HALF_WIDTH),
+        }
+    }
+
+    /// The river's level at the column, and how close it is to the middle of the
+    /// river, from 1 in the middle to 0 on the bank
+    fn at(&self, column: DVec2) -> Option<(i32, f64)> {
+        if column.cmplt(self.min).any() || column.cmpgt(self.max).any() {
+            return None;
+        }
+        self.course
+            .windows(2)
+            .filter_map(|segment| {
+                let ((start, start_level), (end, end_level)) = (segment[0], segment[1]);
+                let along = end - start;
+                let t = ((column - start).dot(along) / along.length_squared()).clamp(0.0, 1.0);
+                let distance = column.distance(start + along * t);
+                let level = (start_level as f64 + (end_level - start_level) as f64 * t).floor();
+                (distance <= RIVER_HALF_WIDTH)
+                    .then_some((level as i32, 1.0 - distance / RIVER_HALF_WIDTH))
+            })
+            .max_by(|(_, a), (_, b)| a.total_cmp(b))
+    }
+}
+
+impl Waterways {
+    pub fn new(noise_seed: u32) -> Self {
+        Waterways {
+            seed: noise_seed as u64,
+            cells: Default::default(),
+        }
+    }
+
+    /// The rivers and lakes that could reach any column from `min` to `max`.
+    /// Looked up once for a whole heightmap, rather than for each of its columns.
+    /// `height` is the terrain's height map.
+    pub fn around(
+        &self,
+        min: IVec2,
+        max: IVec2,
+        height: impl Fn(i32, i32) -> f64,
+        sea_level: i32,
+    ) -> NearbyWaters {
+        let min_cell = min.div_euclid(IVec2::splat(CELL_SIZE)) - IVec2::splat(CELL_REACH);
+        let max_cell = max.div_euclid(IVec2::splat(CELL_SIZE)) + IVec2::splat(CELL_REACH);
+        NearbyWaters(
+            (min_cell.x..=max_cell.x)
+                .flat_map(|x| (min_cell.y..=max_cell.y).map(move |z| IVec2::new(x, z)))
+                .map(|cell| self.cell(cell, &height, sea_level))
+                .filter(|cell| cell.river.is_some() || cell.lake.is_some())
+                .collect(),
+        )
+    }
+
+    /// Drops the cells whose rivers and lakes can't reach within `distance`
+    /// columns of `center`
+    pub fn evict_beyond(&self, center: IVec2, distance: i32) {
+        let center = center.div_euclid(IVec2::splat(CELL_SIZE));
+        let reach = distance / CELL_SIZE + 1 + CELL_REACH;
+        self.cells
+            .retain(|cell| (cell - center).abs().max_element() <= reach);
+    }
+
+    fn cell(
+        &self,
+        cell: IVec2,
+        height: &impl Fn(i32, i32) -> f64,
+        sea_level: i32,
+    ) -> Arc<CellWaters> {
+        self.cells.get_or_insert_with(cell, || {
+            let hash =
+                splitmix(self.seed ^ splitmix(((cell.x as u64) << 32) ^ (cell.y as u32 as u64)));
+            CellWaters {
+                river: (hash % 100 < RIVER_CHANCE)
+                    .then(|| trace_river(cell, hash, height, sea_level))
+                    .flatten(),
+                lake: ((hash >> 8) % 100 < LAKE_CHANCE)
+                    .then(|| find_lake(point_in_cell(cell, hash >> 16), height, sea_level))
+                    .flatten(),
+            }
+        })
+    }
+}
+
+impl NearbyWaters {
+    /// The height of the ground in the column, lowered where a river runs through
+    /// it, and the level inland water fills up to there, if any. `ground` is the
+    /// column's height before carving.
+    pub fn carve(&self, x: i32, z: i32, ground: f64) -> (f64, Option<i32>) {
+        let column = DVec2::new(x as f64, z as f64);
+
+        // Lakes fill their hollows without any carving
+        let lake_level = self
+            .0
+            .iter()
+            .filter_map(|cell| cell.lake)
+            .filter(|lake| {
+                column.distance(lake.bottom) <= LAKE_RADIUS && ground < lake.level as f64
+            })
+            .map(|lake| lake.level)
+            .max();
+        if lake_level.is_some() {
+            return (ground, lake_level);
+        }
+
+        // Where rivers meet, the lower one's level is kept
+        let river = self
+            .0
+            .iter()
+            .filter_map(|cell| cell.river.as_ref()?.at(column))
+            .min_by_key(|(level, _)| *level);
+        if let Some((level, middle)) = river {
+            let bed = level as f64 - RIVER_DEPTH * middle.sqrt();
+            return (ground.min(bed), Some(level));
+        }
+        (ground, None)
+    }
+}
+
+fn ground_at(height: &impl Fn(i32, i32) -> f64, point: DVec2) -> f64 {
+    height(point.x.round() as i32, point.y.round() as i32)
+}
+
+fn point_in_cell(cell: IVec2, hash: u64) -> DVec2 {
+    let hash = splitmix(hash);
+    let offset = IVec2::new(
+        (hash % CELL_SIZE as u64) as i32,
+        ((hash >> 32) % CELL_SIZE as u64) as i32,
+    );
+    (cell * CELL_SIZE + offset).as_dvec2()
+}
+
+/// The points `distance` away from `point` in eight directions
+fn neighbours(point: DVec2, distance: i32) -> impl Iterator<Item = (DVec2, DVec2)> {
+    (0..8).map(move |direction| {
+        let angle = std::f64::consts::TAU * direction as f64 / 8.0;
+        let direction = DVec2::new(angle.cos(), angle.sin());
+        (direction, point + direction * distance as f64)
+    })
+}
+
+/// Follows the river from the highest of a few points in the cell down to the sea.
+/// Its level drops by at least one at every step, cutting a gorge where the ground
+/// rises ahead of it, so it always flows downhill. `None` if it doesn't rise high
+/// enough above the sea, or doesn't reach it.
+fn trace_river(
+    cell: IVec2,
+    hash: u64,
+    height: &impl Fn(i32, i32) -> f64,
+    sea_level: i32,
+) -> Option<River> {
+    let source = (0..SOURCE_CANDIDATES)
+        .map(|candidate| point_in_cell(cell, hash.wrapping_add(candidate)))
+        .max_by(|a, b| ground_at(height, *a).total_cmp(&ground_at(height, *b)))?;
+    let source_ground = ground_at(height, source);
+    if source_ground < sea_level as f64 + MIN_SOURCE_HEIGHT {
+        return None;
+    }
+
+    let mut course = vec![(source, source_ground.floor() as i32 - 1)];
+    let mut heading: Option<DVec2> = None;
+    for _ in 0..MAX_RIVER_STEPS {
+        let (point, level) = *course.last()?;
+        // The lowest ground ahead, never turning back on itself
+        let (direction, next) = neighbours(point, RIVER_STEP)
+            .filter(|(direction, _)| heading.map_or(true, |heading| direction.dot(heading) > 0.0))
+            .min_by(|(_, a), (_, b)| ground_at(height, *a).total_cmp(&ground_at(height, *b)))?;
+        let ground = ground_at(height, next);
+        let next_level = (level - 1).min(ground.floor() as i32 - 1).max(sea_level);
+        course.push((next, next_level));
+        heading = Some(direction);
+        if ground < sea_level as f64 {
+            return Some(River::new(course));
+        }
+    }
+    None
+}
+
+/// Runs downhill from `start` to the bottom of its hollow, and fills the hollow to
+/// just below the lowest point of its shore, so it doesn't spill. `None` if there
+/// is no hollow to fill, or it is below the sea.
+fn find_lake(start: DVec2, height: &impl Fn(i32, i32) -> f64, sea_level: i32) -> Option<Lake> {
+    let mut bottom = start;
+    let mut settled = false;
+    for _ in 0..MAX_LAKE_STEPS {
+        let (_, lowest) = neighbours(bottom, LAKE_STEP)
+            .min_by(|(_, a), (_, b)| ground_at(height, *a).total_cmp(&ground_at(height, *b)))?;
+        if ground_at(height, lowest) >= ground_at(height, bottom) {
+            settled = true;
+            break;
+        }
+        bottom = lowest;
+    }
+    if !settled {
+        return None;
+    }
+    let level = (0..LAKE_SHORE_SAMPLES)
+        .map(|sample| {
+            let angle = std::f64::consts::TAU * sample as f64 / LAKE_SHORE_SAMPLES as f64;
+            ground_at(
+                height,
+                bottom + DVec2::new(angle.cos(), angle.sin()) * LAKE_RADIUS,
+            )
+        })
+        .fold(f64::INFINITY, f64::min)
+        .floor() as i32
+        - 1;
+    (level > sea_level && ground_at(height, bottom) < level as f64)
+        .then_some(Lake { bottom, level })
+}
+
+/// Mixes the bits of `x` thoroughly, for cheap deterministic hashing
+fn splitmix(x: u64) -> u64 {
+    let mut z = x.wrapping_add(0x9E37_79B9_7F4A_7C15);
+    z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+    z ^ (z >> 31)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::waterways::*;
+
+    fn carve(
+        waterways: &Waterways,
+        x: i32,
+        z: i32,
+        height: impl Fn(i32, i32) -> f64,
+    ) -> (f64, Option<i32>) {
+        let column = IVec2::new(x, z);
+        let ground = height(x, z);
+        waterways
+            .around(column, column, height, 1)
+            .carve(x, z, ground)
+    }
+
+    #[test]
+    fn rivers_flow_down_to_the_sea() {
+        // Falling away to the sea beyond x = 1180, with ridges running across it
+        let height = |x: i32, z: i32| {
+            60.0 - x as f64 * 0.05 + 1.5 * (x as f64 / 40.0 + z as f64 / 60.0).sin()
+        };
+        let waterways = Waterways::new(7);
+        let rivers: Vec<Arc<CellWaters>> = (0..4)
+            .flat_map(|x| (0..8).map(move |z| IVec2::new(x, z)))
+            .map(|cell| waterways.cell(cell, &height, 1))
+            .filter(|cell| cell.river.is_some())
+            .collect();
+        assert!(!rivers.is_empty());
+
+        rivers.iter().for_each(|cell| {
+            let course = &cell.river.as_ref().unwrap().course;
+            // Every step is lower than the last, until the river is down to sea level
+            course.windows(2).for_each(|step| {
+                let (level, next_level) = (step[0].1, step[1].1);
+                assert!(next_level < level || level == 1);
+            });
+            let (mouth, mouth_level) = *course.last().unwrap();
+            assert_eq!(mouth_level, 1);
+            assert!(ground_at(&height, mouth) < 1.0);
+            // And there is water all the way along it
+            course.iter().for_each(|(point, level)| {
+                let (ground, water_level) =
+                    carve(&waterways, point.x as i32, point.y as i32, height);
+                assert!(water_level.is_some_and(|water_level| water_level <= *level));
+                assert!(ground < *level as f64);
+            });
+        });
+    }
+
+    #[test]
+    fn lakes_fill_hollows() {
+        // Hollows every 96 columns, 12 deep below their lowest rim
+        let hollows = |x: i32, z: i32| {
+            let wave = |v: i32| (std::f64::consts::TAU * v as f64 / 96.0).sin();
+            20.0 + 6.0 * (wave(x) + wave(z))
+        };
+        let waterways = Waterways::new(3);
+        let lake = (0..8)
+            .flat_map(|x| (0..8).map(move |z| IVec2::new(x, z)))
+            .find_map(|cell| waterways.cell(cell, &hollows, 1).lake)
+            .unwrap();
+        let (bottom_x, bottom_z) = (lake.bottom.x as i32, lake.bottom.y as i32);
+        assert!(hollows(bottom_x, bottom_z) < lake.level as f64);
+
+        // The hollow fills up to one level, and the ground around it stays dry
+        let (_, level) = carve(&waterways, bottom_x, bottom_z, hollows);
+        let level = level.unwrap();
+        assert!(level >= lake.level);
+        (-24..=24).for_each(|offset| {
+            let (x, z) = (bottom_x + offset, bottom_z);
+            let (_, water_level) = carve(&waterways, x, z, hollows);
+            if hollows(x, z) < level as f64 {
+                assert_eq!(water_level, Some(level));
+            } else {
+                assert_eq!(water_level, None);
+            }
+        });
+
+        // A slope has no hollows to fill, and no sea for rivers to reach
+        let slope = |x: i32, z: i32| 20.0 + x as f64 * 0.01 + z as f64 * 0.02;
+        let waterways = Waterways::new(3);
+        (0..8)
+            .flat_map(|x| (0..8).map(move |z| IVec2::new(x, z)))
+            .for_each(|cell| {
+                let waters = waterways.cell(cell, &slope, 1);
+                assert!(waters.lake.is_none() && waters.river.is_none());
+            });
+    }
+
+    #[test]
+    fn far_cells_are_evicted() {
+        let flat = |_: i32, _: i32| 20.0;
+        let waterways = Waterways::new(3);
+        let (near, far) = (IVec2::new(CELL_REACH, 0), IVec2::splat(20));
+        let near_waters = waterways.cell(near, &flat, 1);
+        let far_waters = waterways.cell(far, &flat, 1);
+        waterways.evict_beyond(IVec2::ZERO, 64);
+
+        assert!(Arc::ptr_eq(&near_waters, &waterways.cell(near, &flat, 1)));
+        // Worked out again once evicted
+        assert!(!Arc::ptr_eq(&far_waters, &waterways.cell(far, &flat, 1)));
+    }
+}
@@ -0,0 +1,140 @@
+use crate::core_components::*;
+use crate::lifecycles::LifeCycleSet;
+use crate::sim_rng::{RngStream, SimRng};
+use bevy::prelude::*;
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+use strum_macros::Display;
+
+const MIN_WEATHER_DURATION: f32 = 30.;
+const MAX_WEATHER_DURATION: f32 = 90.;
+/// How often the weather adds or removes a unit of `Water`, in seconds
+const WATER_TICK: f32 = 5.;
+/// Water organisms settle at in clear weather until their `Moisture` is known
+const CLEAR_WATER: u32 = 1;
+const MAX_RAIN_WATER: u32 = 5;
+
+/// Moves the weather between clear skies, rain and drought, which changes
+/// the `Water` available to organisms
+pub struct WeatherPlugin;
+
+impl Plugin for WeatherPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<Weather>()
+            .add_event::<WeatherChanged>()
+            .add_systems(
+                Update,
+                (advance_weather_system, weather_water_system)
+                    .chain()
+                    .in_set(LifeCycleSet),
+            );
+    }
+}
+
+#[derive(Eq, PartialEq, Clone, Copy, Debug, Display, Serialize, Deserialize)]
+pub enum WeatherKind {
+    Clear,
+    Rain,
+    Drought,
+}
+
+impl WeatherKind {
+    /// Clouds block some of the light plants would otherwise get
+    pub fn light_factor(&self) -> f32 {
+        match self {
+            WeatherKind::Rain => 0.5,
+            WeatherKind::Clear | WeatherKind::Drought => 1.,
+        }
+    }
+
+    /// Weather always passes through clear skies between rain and drought
+    fn next(&self, rng: &mut impl Rng) -> WeatherKind {
+        match self {
+            WeatherKind::Clear if rng.gen_bool(0.5) => WeatherKind::Rain,
+            WeatherKind::Clear => WeatherKind::Drought,
+            WeatherKind::Rain | WeatherKind::Drought => WeatherKind::Clear,
+        }
+    }
+}
+
+#[derive(Resource, Clone, Debug, Serialize, Deserialize)]
+pub struct Weather {
+    pub kind: WeatherKind,
+    /// Seconds until the weather changes
+    pub remaining: f32,
+    /// Seconds since `Water` was last adjusted
+    #[serde(default)]
+    pub water_timer: f32,
+}
+
+impl Weather {
+    pub fn new(kind: WeatherKind, remaining: f32) -> Self {
+        Weather {
+            kind,
+            remaining,
+            water_timer: 0.,
+        }
+    }
+}
+
+impl Default for Weather {
+    fn default() -> Self {
+        Weather::new(WeatherKind::Clear, MAX_WEATHER_DURATION)
+    }
+}
+
+/// Sent whenever the weather changes, so audio and visuals can follow it
+#[derive(Event, Clone, Copy, Debug)]
+pub struct WeatherChanged {
+    pub from: WeatherKind,
+    pub to: WeatherKind,
+}
+
+fn advance_weather_system(
+    time: Res<Time>,
+    mut weather: ResMut<Weather>,
+    mut sim_rng: ResMut<SimRng>,
+    mut weather_changes: EventWriter<WeatherChanged>,
+) {
+    weather.remaining -= time.delta_seconds();
+    if weather.remaining <= 0. {
+        let rng = sim_rng.stream(RngStream::Weather);
+        let from = weather.kind;
+        weather.kind = from.next(rng);
+        weather.remaining = rng.gen_range(MIN_WEATHER_DURATION..MAX_WEATHER_DURATION);
+        info!("weather changed from {from} to {}", weather.kind);
+        weather_changes.send(WeatherChanged {
+            from,
+            to: weather.kind,
+        });
+    }
+}
+
+/// Water around organisms moves towards what the weather and the ground's `Moisture`
+/// allow, one unit every `WATER_TICK` seconds: rain tops it up, drought dries out
+/// half of it, and clear skies bring it back to the ground's moisture
+fn weather_water_system(
+    time: Res<Time>,
+    mut weather: ResMut<Weather>,
+    mut query: Query<(&mut Water, Option<&Moisture>), With<Species>>,
+) {
+    weather.water_timer += time.delta_seconds();
+    if weather.water_timer < WATER_TICK {
+        return;
+    }
+    weather.water_timer -= WATER_TICK;
+    let kind = weather.kind;
+    query.for_each_mut(|(mut water, moisture)| {
+        let moisture = moisture.map_or(CLEAR_WATER, |moisture| moisture.0);
+        let target = match kind {
+            WeatherKind::Rain => MAX_RAIN_WATER.max(moisture),
+            WeatherKind::Drought => moisture / 2,
+            WeatherKind::Clear => moisture,
+        };
+        if water.0 < target {
+            water.0 += 1;
+        } else if water.0 > target {
+            water.0 -= 1;
+        }
+    });
+}
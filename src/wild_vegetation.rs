@@ -0,0 +1,145 @@
+use crate::core_components::*;
+use crate::lifecycles::LifeCycleSet;
+use crate::map_setup::TerrainGenerator;
+use crate::organism_index::{OrganismIndex, CHUNK_SIZE};
+use crate::sim_rng::{RngStream, WorldSeed};
+use crate::spawner::spawn_organism;
+use crate::species::SpeciesRegistry;
+use crate::voxel_painting::{is_topsoil_voxel, paint_voxel_unchecked, SetVoxel};
+use crate::GameState;
+use bevy::prelude::*;
+use bevy::utils::HashSet;
+use bevy_voxel_world::prelude::*;
+use rand::Rng;
+
+/// Scatters each species' `wild_organisms_per_chunk` over the surface of chunks as
+/// the voxel world spawns them
+pub struct WildVegetationPlugin;
+
+impl Plugin for WildVegetationPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<PopulatedChunks>().add_systems(
+            Update,
+            wild_vegetation_system
+                .before(LifeCycleSet)
+                .run_if(in_state(GameState::Playing))
+                .run_if(resource_exists::<TerrainGenerator>())
+                .run_if(resource_exists::<SpeciesRegistry>()),
+        );
+    }
+}
+
+/// Chunks that have already had their wild vegetation. Chunks are despawned and
+/// spawned again as the camera moves, but organisms stay, so each chunk only gets it once.
+#[derive(Resource, Default, Clone, Debug, Deref, DerefMut)]
+pub struct PopulatedChunks(pub HashSet<IVec3>);
+
+/// Where each species' wild organisms grow in the chunk at `chunk_key`, on soil
+/// with air above it. Each is only kept as often as its species prefers the biome
+/// it lands in, so every biome has its own mix. This only depends on the seed and
+/// the generated terrain, so a chunk always gets the same vegetation.
+pub fn wild_organism_positions(
+    terrain: &TerrainGenerator,
+    species_registry: &SpeciesRegistry,
+    world_seed: WorldSeed,
+    chunk_key: IVec3,
+) -> Vec<(Species, IVec3)> {
+    let mut rng = world_seed.chunk_rng(RngStream::WildVegetation, chunk_key);
+    let chunk_min = chunk_key * CHUNK_SIZE;
+    species_registry
+        .iter()
+        .flat_map(|(species, species_def)| {
+            (0..species_def.wild_organisms_per_chunk).map(move |_| (species, species_def))
+        })
+        .fold(Vec::new(), |mut organisms, (species, species_def)| {
+            let (x, z) = (
+                chunk_min.x + rng.gen_range(0..CHUNK_SIZE),
+                chunk_min.z + rng.gen_range(0..CHUNK_SIZE),
+            );
+            let kept = rng.gen::<f32>() < species_def.biome_preference(terrain.biome(x, z));
+            let (surface, voxel) = terrain.surface_voxel(x, z);
+            let pos = surface + IVec3::Y;
+            // The column's surface may well be in a chunk above or below this one
+            if kept
+                && is_topsoil_voxel(voxel)
+                && OrganismIndex::chunk_of(pos) == chunk_key
+                && !organisms.iter().any(|(_, other)| *other == pos)
+            {
+                organisms.push((species, pos));
+            }
+            organisms
+        })
+}
+
+#[allow(clippy::too_many_arguments)]
+fn wild_vegetation_system(
+    mut commands: Commands,
+    mut chunk_spawns: EventReader<ChunkWillSpawn>,
+    terrain: Res<TerrainGenerator>,
+    species_registry: Res<SpeciesRegistry>,
+    world_seed: Res<WorldSeed>,
+    mut populated_chunks: ResMut<PopulatedChunks>,
+    mut index: ResMut<OrganismIndex>,
+    mut voxel_edits: EventWriter<SetVoxel>,
+) {
+    chunk_spawns
+        .read()
+        .filter(|chunk_spawn| populated_chunks.insert(chunk_spawn.chunk_key))
+        .flat_map(|chunk_spawn| {
+            wild_organism_positions(
+                &terrain,
+                &species_registry,
+                *world_seed,
+                chunk_spawn.chunk_key,
+            )
+        })
+        .for_each(|(species, pos)| {
+            if index.is_occupied(pos) {
+                return;
+            }
+            let organism = spawn_organism(&mut commands, species, LifePhase::Seed, pos, None);
+            index.insert(organism, pos);
+            let block_type = species_registry.get(species).block_type(&SEED_PHASE);
+            paint_voxel_unchecked(&mut voxel_edits, pos, block_type);
+        });
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::biome::Biome;
+    use crate::wild_vegetation::*;
+    use crate::world_gen::WorldGenSettings;
+
+    #[test]
+    fn chunks_always_get_the_same_vegetation() {
+        let registry = SpeciesRegistry::for_tests();
+        let terrain = TerrainGenerator::new(&WorldGenSettings {
+            seed: 7,
+            ..default()
+        });
+        // A meadow somewhere inland, so the whole chunk is above the sea
+        let (inland, _) = (0..10_000)
+            .map(|x| terrain.surface_voxel(x * CHUNK_SIZE, 0))
+            .find(|(pos, _)| {
+                (8..24).contains(&pos.y) && terrain.biome(pos.x, pos.z) == Biome::Meadow
+            })
+            .unwrap();
+        let chunk_key = OrganismIndex::chunk_of(inland);
+
+        let organisms = wild_organism_positions(&terrain, &registry, WorldSeed(7), chunk_key);
+        assert!(!organisms.is_empty());
+        assert!(organisms.iter().all(|(_, pos)| {
+            OrganismIndex::chunk_of(*pos) == chunk_key
+                && terrain.surface_voxel(pos.x, pos.z).0 == *pos - IVec3::Y
+                && terrain.biome(pos.x, pos.z) != Biome::Alpine
+        }));
+        assert_eq!(
+            organisms,
+            wild_organism_positions(&terrain, &registry, WorldSeed(7), chunk_key)
+        );
+        assert_ne!(
+            organisms,
+            wild_organism_positions(&terrain, &registry, WorldSeed(8), chunk_key)
+        );
+    }
+}
@@ -0,0 +1,304 @@
+use bevy::prelude::*;
+use serde::{Deserialize, Serialize};
+use std::ops::RangeInclusive;
+use strum_macros::EnumIter;
+use thiserror::Error;
+
+/// The seed worlds are generated from unless another is given
+const DEFAULT_SEED: u64 = 0;
+
+/// Everything the terrain is generated from. The same settings always give the
+/// same world, so they are recorded in save files. Set from the "New World"
+/// screen in the menu, or with command line flags, see `WorldGenSettings::from_args`.
+#[derive(Resource, Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct WorldGenSettings {
+    /// The `WorldSeed` is always this seed, see `SimRngPlugin`
+    pub seed: u64,
+    pub octaves: usize,
+    pub frequency: f64,
+    pub lacunarity: f64,
+    pub persistence: f64,
+    /// Height of the tallest hills, in voxels
+    pub height_scale: f64,
+    /// The sea fills everything below this that isn't ground
+    pub sea_level: i32,
+    /// How far around the camera chunks are generated, in 32 voxel chunks
+    pub spawning_distance: u32,
+    /// Hollow out caves underground. Worlds saved before there were caves have none.
+    #[serde(default)]
+    pub caves: bool,
+}
+
+/// Always the same seed, so headless runs, benchmarks and tests are repeatable.
+/// A fresh one can be picked with "Random seed" in the menu.
+impl Default for WorldGenSettings {
+    fn default() -> Self {
+        WorldGenSettings {
+            seed: DEFAULT_SEED,
+            octaves: 5,
+            frequency: 1.1,
+            lacunarity: 2.8,
+            persistence: 0.4,
+            height_scale: 50.0,
+            sea_level: 1,
+            spawning_distance: 25,
+            caves: true,
+        }
+    }
+}
+
+#[derive(Debug, Error, PartialEq)]
+pub enum WorldGenArgsError {
+    #[error("unknown flag '{0}'")]
+    UnknownFlag(String),
+    #[error("'{0}' needs a value")]
+    MissingValue(String),
+    #[error("invalid value '{value}' for '{flag}'")]
+    InvalidValue { flag: String, value: String },
+    #[error("'{flag}' must be between {min} and {max}, not {value}")]
+    OutOfRange {
+        flag: String,
+        value: String,
+        min: f64,
+        max: f64,
+    },
+}
+
+/// The settings that can be changed on the "New World" screen, in the order they are shown
+#[derive(Clone, Copy, Debug, PartialEq, Eq, EnumIter)]
+pub enum WorldGenSetting {
+    Seed,
+    Octaves,
+    Frequency,
+    Lacunarity,
+    Persistence,
+    HeightScale,
+    SeaLevel,
+    SpawningDistance,
+    Caves,
+}
+
+impl WorldGenSetting {
+    pub fn label(self) -> &'static str {
+        match self {
+            WorldGenSetting::Seed => "Seed",
+            WorldGenSetting::Octaves => "Octaves",
+            WorldGenSetting::Frequency => "Frequency",
+            WorldGenSetting::Lacunarity => "Lacunarity",
+            WorldGenSetting::Persistence => "Persistence",
+            WorldGenSetting::HeightScale => "Height scale",
+            WorldGenSetting::SeaLevel => "Sea level",
+            WorldGenSetting::SpawningDistance => "View distance",
+            WorldGenSetting::Caves => "Caves",
+        }
+    }
+
+    /// The command line flag for the setting, e.g. `--height-scale 60`
+    pub fn flag(self) -> &'static str {
+        match self {
+            WorldGenSetting::Seed => "--seed",
+            WorldGenSetting::Octaves => "--octaves",
+            WorldGenSetting::Frequency => "--frequency",
+            WorldGenSetting::Lacunarity => "--lacunarity",
+            WorldGenSetting::Persistence => "--persistence",
+            WorldGenSetting::HeightScale => "--height-scale",
+            WorldGenSetting::SeaLevel => "--sea-level",
+            WorldGenSetting::SpawningDistance => "--spawning-distance",
+            WorldGenSetting::Caves => "--caves",
+        }
+    }
+
+    pub fn value_text(self, settings: &WorldGenSettings) -> String {
+        match self {
+            WorldGenSetting::Seed => settings.seed.to_string(),
+            WorldGenSetting::Octaves => settings.octaves.to_string(),
+            WorldGenSetting::Frequency => format!("{:.2}", settings.frequency),
+            WorldGenSetting::Lacunarity => format!("{:.2}", settings.lacunarity),
+            WorldGenSetting::Persistence => format!("{:.2}", settings.persistence),
+            WorldGenSetting::HeightScale => format!("{:.0}", settings.height_scale),
+            WorldGenSetting::SeaLevel => settings.sea_level.to_string(),
+            WorldGenSetting::SpawningDistance => settings.spawning_distance.to_string(),
+            WorldGenSetting::Caves => if settings.caves { "On" } else { "Off" }.to_string(),
+        }
+    }
+
+    /// The sensible values for the setting, which both the "New World" screen and
+    /// the command line keep to. `None` for settings that can be anything.
+    pub fn range(self) -> Option<RangeInclusive<f64>> {
+        match self {
+            WorldGenSetting::Seed | WorldGenSetting::Caves => None,
+            WorldGenSetting::Octaves => Some(1.0..=8.0),
+            WorldGenSetting::Frequency => Some(0.1..=5.0),
+            WorldGenSetting::Lacunarity => Some(1.0..=4.0),
+            WorldGenSetting::Persistence => Some(0.05..=1.0),
+            WorldGenSetting::HeightScale => Some(5.0..=200.0),
+            WorldGenSetting::SeaLevel => Some(-50.0..=50.0),
+            WorldGenSetting::SpawningDistance => Some(4.0..=40.0),
+        }
+    }
+
+    /// The setting's value, for those that have a `range`
+    fn number(self, settings: &WorldGenSettings) -> Option<f64> {
+        match self {
+            WorldGenSetting::Seed | WorldGenSetting::Caves => None,
+            WorldGenSetting::Octaves => Some(settings.octaves as f64),
+            WorldGenSetting::Frequency => Some(settings.frequency),
+            WorldGenSetting::Lacunarity => Some(settings.lacunarity),
+            WorldGenSetting::Persistence => Some(settings.persistence),
+            WorldGenSetting::HeightScale => Some(settings.height_scale),
+            WorldGenSetting::SeaLevel => Some(settings.sea_level as f64),
+            WorldGenSetting::SpawningDistance => Some(settings.spawning_distance as f64),
+        }
+    }
+
+    /// Moves the setting `steps` steps up or down, keeping it within its `range`
+    pub fn step(self, settings: &mut WorldGenSettings, steps: i32) {
+        let clamp = |value: f64| {
+            self.range()
+                .map_or(value, |range| value.clamp(*range.start(), *range.end()))
+        };
+        let step =
+            |value: f64, size: f64| clamp(((value + size * steps as f64) / size).round() * size);
+        match self {
+            WorldGenSetting::Seed => {
+                settings.seed = settings.seed.wrapping_add_signed(steps as i64)
+            }
+            WorldGenSetting::Octaves => {
+                settings.octaves = clamp((settings.octaves as i32 + steps) as f64) as usize
+            }
+            WorldGenSetting::Frequency => settings.frequency = step(settings.frequency, 0.1),
+            WorldGenSetting::Lacunarity => settings.lacunarity = step(settings.lacunarity, 0.1),
+            WorldGenSetting::Persistence => settings.persistence = step(settings.persistence, 0.05),
+            WorldGenSetting::HeightScale => {
+                settings.height_scale = step(settings.height_scale, 5.0)
+            }
+            WorldGenSetting::SeaLevel => {
+                settings.sea_level = clamp((settings.sea_level + steps) as f64) as i32
+            }
+            WorldGenSetting::SpawningDistance => {
+                settings.spawning_distance =
+                    clamp((settings.spawning_distance as i32 + steps) as f64) as u32
+            }
+            WorldGenSetting::Caves => settings.caves = !settings.caves,
+        }
+    }
+
+    fn parse(self, settings: &mut WorldGenSettings, value: &str) -> Result<(), ()> {
+        fn parse<T: std::str::FromStr>(value: &str) -> Result<T, ()> {
+            value.parse().map_err(|_| ())
+        }
+        match self {
+            WorldGenSetting::Seed => settings.seed = parse(value)?,
+            WorldGenSetting::Octaves => settings.octaves = parse(value)?,
+            WorldGenSetting::Frequency => settings.frequency = parse(value)?,
+            WorldGenSetting::Lacunarity => settings.lacunarity = parse(value)?,
+            WorldGenSetting::Persistence => settings.persistence = parse(value)?,
+            WorldGenSetting::HeightScale => settings.height_scale = parse(value)?,
+            WorldGenSetting::SeaLevel => settings.sea_level = parse(value)?,
+            WorldGenSetting::SpawningDistance => settings.spawning_distance = parse(value)?,
+            WorldGenSetting::Caves => settings.caves = parse(value)?,
+        }
+        Ok(())
+    }
+}
+
+impl WorldGenSettings {
+    /// Reads flags like `--seed 42 --sea-level 3` over the defaults; see
+    /// `WorldGenSetting::flag` for the names. `args` shouldn't include the program name.
+    pub fn from_args(args: impl IntoIterator<Item = String>) -> Result<Self, WorldGenArgsError> {
+        use strum::IntoEnumIterator;
+        let mut settings = WorldGenSettings::default();
+        let mut args = args.into_iter();
+        while let Some(flag) = args.next() {
+            let setting = WorldGenSetting::iter()
+                .find(|setting| setting.flag() == flag)
+                .ok_or_else(|| WorldGenArgsError::UnknownFlag(flag.clone()))?;
+            let value = args
+                .next()
+                .ok_or_else(|| WorldGenArgsError::MissingValue(flag.clone()))?;
+            if setting.parse(&mut settings, &value).is_err() {
+                return Err(WorldGenArgsError::InvalidValue { flag, value });
+            }
+            if let (Some(range), Some(number)) = (setting.range(), setting.number(&settings)) {
+                if !range.contains(&number) {
+                    return Err(WorldGenArgsError::OutOfRange {
+                        flag,
+                        value,
+                        min: *range.start(),
+                        max: *range.end(),
+                    });
+                }
+            }
+        }
+        Ok(settings)
+    }
+
+    /// The flags `from_args` takes, one per line with the values each can be
+    pub fn usage() -> String {
+        use strum::IntoEnumIterator;
+        let flags = WorldGenSetting::iter().map(|setting| {
+            let values = match (setting, setting.range()) {
+                (WorldGenSetting::Caves, _) => "true|false".to_string(),
+                (_, Some(range)) => format!("{}..={}", range.start(), range.end()),
+                (_, None) => "number".to_string(),
+            };
+            format!("\n  {} <{values}>", setting.flag())
+        });
+        format!("Flags:{}", flags.collect::<String>())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::world_gen::*;
+
+    #[test]
+    fn flags_override_defaults() {
+        let args =
+            |args: &[&str]| WorldGenSettings::from_args(args.iter().map(|arg| arg.to_string()));
+        let settings = args(&[
+            "--seed",
+            "42",
+            "--height-scale",
+            "60.5",
+            "--sea-level",
+            "-2",
+        ])
+        .unwrap();
+        assert_eq!(settings.seed, 42);
+        assert_eq!(settings.height_scale, 60.5);
+        assert_eq!(settings.sea_level, -2);
+        assert_eq!(settings.octaves, WorldGenSettings::default().octaves);
+
+        assert_eq!(
+            args(&["--seed"]),
+            Err(WorldGenArgsError::MissingValue("--seed".to_string()))
+        );
+        assert!(matches!(
+            args(&["--octaves", "many"]),
+            Err(WorldGenArgsError::InvalidValue { .. })
+        ));
+        assert!(matches!(
+            args(&["--octaves", "0"]),
+            Err(WorldGenArgsError::OutOfRange { .. })
+        ));
+        assert!(matches!(
+            args(&["--spawning-distance", "1000"]),
+            Err(WorldGenArgsError::OutOfRange { .. })
+        ));
+        assert!(matches!(
+            args(&["--size", "3"]),
+            Err(WorldGenArgsError::UnknownFlag(_))
+        ));
+    }
+
+    #[test]
+    fn usage_lists_every_flag() {
+        use strum::IntoEnumIterator;
+        let usage = WorldGenSettings::usage();
+        for setting in WorldGenSetting::iter() {
+            assert!(usage.contains(setting.flag()), "{usage}");
+        }
+        assert!(usage.contains("--octaves <1..=8>"), "{usage}");
+    }
+}